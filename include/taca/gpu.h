@@ -1,37 +1,176 @@
 #pragma once
 
+#include <stdbool.h>
 #include <stdint.h>
 #include <webgpu.h>
 
 struct taca_gpu_BufferImpl;
 typedef struct taca_gpu_BufferImpl* taca_gpu_Buffer;
 
+struct taca_gpu_ComputeImpl;
+typedef struct taca_gpu_ComputeImpl* taca_gpu_Compute;
+
+struct taca_gpu_PipelineImpl;
+typedef struct taca_gpu_PipelineImpl* taca_gpu_Pipeline;
+
+struct taca_gpu_SamplerImpl;
+typedef struct taca_gpu_SamplerImpl* taca_gpu_Sampler;
+
 struct taca_gpu_ShaderImpl;
 typedef struct taca_gpu_ShaderImpl* taca_gpu_Shader;
 
 struct taca_gpu_TextureImpl;
 typedef struct taca_gpu_TextureImpl* taca_gpu_Texture;
 
-// TODO Some TextureEx or TextureDetail for more detail?
 typedef struct taca_gpu_TextureInfo {
+    // Supports R8Unorm, RG8Unorm, RGBA8Unorm, BGRA8Unorm and their Srgb forms,
+    // plus R16Float, RG16Float, RGBA16Float, R32Float, RG32Float, and
+    // RGBA32Float. The 32-bit floats need non-filtering samplers. BC, ETC2, and
+    // ASTC compressed formats work where taca_gpu_textureFormatSupported says.
     WGPUTextureFormat format;
     uint32_t binding;
     uint32_t width;
     uint32_t height;
 } taca_gpu_TextureInfo;
 
-// TODO Need different pipelines for different shaders or entry points.
+// Extends taca_gpu_TextureInfo with mip levels.
+typedef struct taca_gpu_TextureDetail {
+    WGPUTextureFormat format;
+    uint32_t binding;
+    uint32_t width;
+    uint32_t height;
+    // Zero means a full chain down to 1x1, which is also the most allowed.
+    uint32_t mipLevelCount;
+    // Box filters later levels from the first, including after writes to it.
+    // Only for uncompressed formats with 8-bit unorm or float channels.
+    bool generateMips;
+} taca_gpu_TextureDetail;
+
+typedef enum taca_gpu_BlendMode {
+    // The pipeline blend when overriding, or else opaque.
+    taca_gpu_BlendMode_Default = 0,
+    taca_gpu_BlendMode_Opaque = 1,
+    // Straight alpha.
+    taca_gpu_BlendMode_Alpha = 2,
+    // Color already multiplied by alpha.
+    taca_gpu_BlendMode_Premultiplied = 3,
+    taca_gpu_BlendMode_Additive = 4,
+    taca_gpu_BlendMode_Multiply = 5,
+    taca_gpu_BlendMode_Force32 = 0x7FFFFFFF
+} taca_gpu_BlendMode;
+
+// Ordered so zero is the default.
+typedef enum taca_gpu_Topology {
+    taca_gpu_Topology_TriangleList = 0,
+    taca_gpu_Topology_TriangleStrip = 1,
+    taca_gpu_Topology_LineList = 2,
+    taca_gpu_Topology_LineStrip = 3,
+    taca_gpu_Topology_PointList = 4,
+    taca_gpu_Topology_Force32 = 0x7FFFFFFF
+} taca_gpu_Topology;
+
+// Zeroed fields give defaults matching the default pipeline.
+typedef struct taca_gpu_PipelineInfo {
+    // Null means vs_main.
+    const char* vertexEntry;
+    // Null means fs_main.
+    const char* fragmentEntry;
+    taca_gpu_Topology topology;
+    WGPUCullMode cullMode;
+    WGPUFrontFace frontFace;
+    // Undefined means Less.
+    WGPUCompareFunction depthCompare;
+    // Test depth without writing it, such as for transparency or skyboxes.
+    bool depthReadOnly;
+    // Null means use blendMode.
+    const WGPUBlendState* blend;
+    // Ignored unless useWriteMask, so zeroed info writes all.
+    WGPUColorWriteMaskFlags writeMask;
+    taca_gpu_BlendMode blendMode;
+    // Use writeMask even if zero, such as for depth only pipelines.
+    bool useWriteMask;
+} taca_gpu_PipelineInfo;
+
 taca_EXPORT taca_gpu_Shader taca_gpu_shaderCreate(const char* wgsl);
 
+typedef struct taca_gpu_FrameOptions {
+    WGPUColor clearColor;
+    float clearDepth;
+    // Keep the previous frame instead of clearing.
+    bool loadColor;
+    bool loadDepth;
+} taca_gpu_FrameOptions;
+
+// Null entry means cs_main.
+taca_EXPORT taca_gpu_Compute taca_gpu_computeCreate(taca_gpu_Shader shader, const char* entry);
+// Applies to later dispatches. Null uses the first compute.
+taca_EXPORT void taca_gpu_computeUse(taca_gpu_Compute compute);
+// Runs outside any render pass, so later draws in the frame keep what's
+// already drawn.
+taca_EXPORT void taca_gpu_dispatch(uint32_t x, uint32_t y, uint32_t z);
+
+// Null info uses defaults.
+taca_EXPORT taca_gpu_Pipeline taca_gpu_pipelineCreate(taca_gpu_Shader shader, const taca_gpu_PipelineInfo* info);
+// Applies to later draws. Null uses the first shader with default entry points.
+taca_EXPORT void taca_gpu_pipelineUse(taca_gpu_Pipeline pipeline);
+// Overrides pipeline blending for later draws until set back to Default.
+taca_EXPORT void taca_gpu_blendModeUse(taca_gpu_BlendMode mode);
+
 taca_EXPORT taca_gpu_Buffer taca_gpu_indexBufferCreate(size_t size, const void* data, WGPUIndexFormat format, taca_gpu_Buffer vertex);
+// Read-write storage is only visible to fragment and compute shaders. Fill
+// with taca_gpu_bufferWrite.
+taca_EXPORT taca_gpu_Buffer taca_gpu_storageBufferCreate(size_t size, uint32_t binding, bool readOnly);
 taca_EXPORT taca_gpu_Buffer taca_gpu_uniformBufferCreate(size_t size, uint32_t binding);
 taca_EXPORT taca_gpu_Buffer taca_gpu_vertexBufferCreate(size_t size, const void* data, const WGPUVertexBufferLayout* layout);
 
+// Color or depth texture to draw to and then sample at its binding in later
+// passes, such as for post-processing or shadow maps. Takes uncompressed color
+// formats or Depth16Unorm, Depth24Plus, and Depth32Float.
+taca_EXPORT taca_gpu_Texture taca_gpu_renderTargetCreate(const taca_gpu_TextureInfo* info);
+// Draws to the given render targets, where either can be null, or to the
+// frame if both are. Each target clears like the frame on its first pass each
+// frame. Attached targets sample as blank until drawing elsewhere, and
+// presenting switches back to the frame. Color and depth targets need matching
+// sizes.
+taca_EXPORT void taca_gpu_renderTargetUse(taca_gpu_Texture color, taca_gpu_Texture depth);
+// Binds for fragment and compute shaders. Null descriptor gives linear
+// filtering with clamped addressing. Samplers with compare are for
+// sampler_comparison, such as shadow maps.
+taca_EXPORT taca_gpu_Sampler taca_gpu_samplerCreate(uint32_t binding, const WGPUSamplerDescriptor* descriptor);
 taca_EXPORT taca_gpu_Texture taca_gpu_textureCreate(const void* data, const taca_gpu_TextureInfo* info);
+// Data is only for the first level.
+taca_EXPORT taca_gpu_Texture taca_gpu_textureCreateEx(const void* data, const taca_gpu_TextureDetail* detail);
+// Compressed formats depend on the adapter, so check before loading assets.
+taca_EXPORT bool taca_gpu_textureFormatSupported(WGPUTextureFormat format);
+// Starts zeroed. Compute shaders bind it as a write-only storage texture,
+// while render shaders sample it like any other.
+taca_EXPORT taca_gpu_Texture taca_gpu_storageTextureCreate(const taca_gpu_TextureInfo* info);
+// Replaces a region of the first level from tightly packed rows, such as for
+// video frames or glyph atlases. Compressed regions align to blocks, as do
+// compressed texture sizes. Not for render targets.
+taca_EXPORT void taca_gpu_textureWrite(taca_gpu_Texture texture, uint32_t x, uint32_t y, uint32_t width, uint32_t height, const void* data);
+// Replaces one whole level, sized for that level.
+taca_EXPORT void taca_gpu_textureWriteMip(taca_gpu_Texture texture, uint32_t level, const void* data);
 
-// Presume full refill of same buffer size by default.
+// Keeps existing contents that fit, with any new space zeroed.
+taca_EXPORT void taca_gpu_bufferResize(taca_gpu_Buffer buffer, size_t size);
+// Full refill of the current buffer size.
 taca_EXPORT void taca_gpu_bufferWrite(taca_gpu_Buffer buffer, const void* data);
+// Only changed ranges get uploaded before the next draw.
+taca_EXPORT void taca_gpu_bufferWriteRange(taca_gpu_Buffer buffer, size_t offset, size_t size, const void* data);
+// Sets the clear color for later frames, or null to keep the previous frame.
+taca_EXPORT void taca_gpu_clear(const WGPUColor* color);
 taca_EXPORT void taca_gpu_draw(taca_gpu_Buffer buffer);
+// The instance buffer needs a layout with WGPUVertexStepMode_Instance. Zero
+// count draws all instances in the buffer.
+taca_EXPORT void taca_gpu_drawInstanced(taca_gpu_Buffer buffer, taca_gpu_Buffer instanceBuffer, uint32_t count);
+// Draws part of a buffer, counting indices for index buffers or else vertices.
+// Zero count draws through the end. Base vertex offsets each index, so it only
+// applies to index buffers. Ranges past the end of the buffer fail.
+taca_EXPORT void taca_gpu_drawRange(taca_gpu_Buffer buffer, uint32_t first, uint32_t count, int32_t baseVertex);
+// Applies to later frames. Null restores clearing to transparent black and
+// depth 1.
+taca_EXPORT void taca_gpu_frameOptions(const taca_gpu_FrameOptions* options);
 taca_EXPORT void taca_gpu_present(void);
 
 // WGPU_EXPORT void wgpuRenderPassEncoderSetBindGroup(WGPURenderPassEncoder renderPassEncoder, uint32_t groupIndex, WGPUBindGroup group, uint32_t dynamicOffsetCount, uint32_t const * dynamicOffsets);
@@ -25,6 +25,45 @@
 
 // TODO Replace all with script to generate json.
 
+// Follows the w3c standard gamepad layout.
+typedef enum taca_GamepadButton {
+    taca_GamepadButton_South = 0,
+    taca_GamepadButton_East = 1,
+    taca_GamepadButton_West = 2,
+    taca_GamepadButton_North = 3,
+    taca_GamepadButton_LeftBumper = 4,
+    taca_GamepadButton_RightBumper = 5,
+    taca_GamepadButton_LeftTrigger = 6,
+    taca_GamepadButton_RightTrigger = 7,
+    taca_GamepadButton_Select = 8,
+    taca_GamepadButton_Start = 9,
+    taca_GamepadButton_LeftStick = 10,
+    taca_GamepadButton_RightStick = 11,
+    taca_GamepadButton_DPadUp = 12,
+    taca_GamepadButton_DPadDown = 13,
+    taca_GamepadButton_DPadLeft = 14,
+    taca_GamepadButton_DPadRight = 15,
+    taca_GamepadButton_Mode = 16,
+    taca_GamepadButton_Force32 = 0x7FFFFFFF
+} taca_GamepadButton;
+
+// Sticks have y up.
+typedef enum taca_GamepadAxis {
+    taca_GamepadAxis_LeftX = 0,
+    taca_GamepadAxis_LeftY = 1,
+    taca_GamepadAxis_RightX = 2,
+    taca_GamepadAxis_RightY = 3,
+    taca_GamepadAxis_Force32 = 0x7FFFFFFF
+} taca_GamepadAxis;
+
+typedef enum taca_GamepadEventType {
+    taca_GamepadEventType_Connect = 1,
+    taca_GamepadEventType_Disconnect = 2,
+    taca_GamepadEventType_Button = 3,
+    taca_GamepadEventType_Axis = 4,
+    taca_GamepadEventType_Force32 = 0x7FFFFFFF
+} taca_GamepadEventType;
+
 typedef enum taca_KeyCode {
     taca_KeyCode_Undefined = 0,
     taca_KeyCode_Left = 1,
@@ -33,33 +72,203 @@ typedef enum taca_KeyCode {
     taca_KeyCode_Down = 4,
     taca_KeyCode_PageUp = 5,
     taca_KeyCode_PageDown = 6,
+    taca_KeyCode_Home = 7,
+    taca_KeyCode_End = 8,
+    taca_KeyCode_Insert = 9,
+    taca_KeyCode_Delete = 10,
+    taca_KeyCode_Backspace = 11,
+    taca_KeyCode_Tab = 12,
+    taca_KeyCode_Enter = 13,
+    taca_KeyCode_Escape = 14,
+    taca_KeyCode_Space = 15,
+    taca_KeyCode_Digit0 = 16,
+    taca_KeyCode_Digit1 = 17,
+    taca_KeyCode_Digit2 = 18,
+    taca_KeyCode_Digit3 = 19,
+    taca_KeyCode_Digit4 = 20,
+    taca_KeyCode_Digit5 = 21,
+    taca_KeyCode_Digit6 = 22,
+    taca_KeyCode_Digit7 = 23,
+    taca_KeyCode_Digit8 = 24,
+    taca_KeyCode_Digit9 = 25,
+    taca_KeyCode_A = 26,
+    taca_KeyCode_B = 27,
+    taca_KeyCode_C = 28,
+    taca_KeyCode_D = 29,
+    taca_KeyCode_E = 30,
+    taca_KeyCode_F = 31,
+    taca_KeyCode_G = 32,
+    taca_KeyCode_H = 33,
+    taca_KeyCode_I = 34,
+    taca_KeyCode_J = 35,
+    taca_KeyCode_K = 36,
+    taca_KeyCode_L = 37,
+    taca_KeyCode_M = 38,
+    taca_KeyCode_N = 39,
+    taca_KeyCode_O = 40,
+    taca_KeyCode_P = 41,
+    taca_KeyCode_Q = 42,
+    taca_KeyCode_R = 43,
+    taca_KeyCode_S = 44,
+    taca_KeyCode_T = 45,
+    taca_KeyCode_U = 46,
+    taca_KeyCode_V = 47,
+    taca_KeyCode_W = 48,
+    taca_KeyCode_X = 49,
+    taca_KeyCode_Y = 50,
+    taca_KeyCode_Z = 51,
+    taca_KeyCode_F1 = 52,
+    taca_KeyCode_F2 = 53,
+    taca_KeyCode_F3 = 54,
+    taca_KeyCode_F4 = 55,
+    taca_KeyCode_F5 = 56,
+    taca_KeyCode_F6 = 57,
+    taca_KeyCode_F7 = 58,
+    taca_KeyCode_F8 = 59,
+    taca_KeyCode_F9 = 60,
+    taca_KeyCode_F10 = 61,
+    taca_KeyCode_F11 = 62,
+    taca_KeyCode_F12 = 63,
+    taca_KeyCode_ShiftLeft = 64,
+    taca_KeyCode_ShiftRight = 65,
+    taca_KeyCode_ControlLeft = 66,
+    taca_KeyCode_ControlRight = 67,
+    taca_KeyCode_AltLeft = 68,
+    taca_KeyCode_AltRight = 69,
+    taca_KeyCode_SuperLeft = 70,
+    taca_KeyCode_SuperRight = 71,
+    taca_KeyCode_Minus = 72,
+    taca_KeyCode_Equal = 73,
+    taca_KeyCode_BracketLeft = 74,
+    taca_KeyCode_BracketRight = 75,
+    taca_KeyCode_Backslash = 76,
+    taca_KeyCode_Semicolon = 77,
+    taca_KeyCode_Quote = 78,
+    taca_KeyCode_Backquote = 79,
+    taca_KeyCode_Comma = 80,
+    taca_KeyCode_Period = 81,
+    taca_KeyCode_Slash = 82,
+    taca_KeyCode_CapsLock = 83,
+    taca_KeyCode_NumLock = 84,
+    taca_KeyCode_ScrollLock = 85,
+    taca_KeyCode_PrintScreen = 86,
+    taca_KeyCode_Pause = 87,
+    taca_KeyCode_ContextMenu = 88,
+    taca_KeyCode_Numpad0 = 89,
+    taca_KeyCode_Numpad1 = 90,
+    taca_KeyCode_Numpad2 = 91,
+    taca_KeyCode_Numpad3 = 92,
+    taca_KeyCode_Numpad4 = 93,
+    taca_KeyCode_Numpad5 = 94,
+    taca_KeyCode_Numpad6 = 95,
+    taca_KeyCode_Numpad7 = 96,
+    taca_KeyCode_Numpad8 = 97,
+    taca_KeyCode_Numpad9 = 98,
+    taca_KeyCode_NumpadAdd = 99,
+    taca_KeyCode_NumpadSubtract = 100,
+    taca_KeyCode_NumpadMultiply = 101,
+    taca_KeyCode_NumpadDivide = 102,
+    taca_KeyCode_NumpadDecimal = 103,
+    taca_KeyCode_NumpadEnter = 104,
     taca_KeyCode_Force32 = 0x7FFFFFFF
 } taca_KeyCode;
 
+typedef enum taca_KeyModifiers {
+    taca_KeyModifiers_None = 0,
+    taca_KeyModifiers_Shift = 1,
+    taca_KeyModifiers_Control = 2,
+    taca_KeyModifiers_Alt = 4,
+    taca_KeyModifiers_Super = 8,
+    taca_KeyModifiers_Force32 = 0x7FFFFFFF
+} taca_KeyModifiers;
+
+typedef enum taca_MouseButton {
+    taca_MouseButton_Undefined = 0,
+    taca_MouseButton_Left = 1,
+    taca_MouseButton_Right = 2,
+    taca_MouseButton_Middle = 3,
+    // Extra buttons count up from here.
+    taca_MouseButton_Other = 4,
+    taca_MouseButton_Force32 = 0x7FFFFFFF
+} taca_MouseButton;
+
 typedef struct taca_Vec2 {
     int32_t x;
     int32_t y;
 } taca_Vec2;
 
+typedef struct taca_Vec2f {
+    float x;
+    float y;
+} taca_Vec2f;
+
 typedef enum taca_WindowEventType {
     taca_WindowEventType_Close = 1,
     taca_WindowEventType_Key = 2,
     taca_WindowEventType_Redraw = 3,
     taca_WindowEventType_Resize = 4,
+    taca_WindowEventType_MouseMove = 5,
+    taca_WindowEventType_MouseButton = 6,
+    taca_WindowEventType_MouseWheel = 7,
+    taca_WindowEventType_Text = 8,
+    taca_WindowEventType_Gamepad = 9,
     taca_WindowEventType_Force32 = 0x7FFFFFFF
 } taca_WindowEventType;
 
+typedef struct taca_GamepadEvent {
+    uint32_t pad;
+    taca_GamepadEventType type;
+    // Button or axis, depending on type.
+    uint32_t index;
+    float value;
+} taca_GamepadEvent;
+
 typedef struct taca_KeyEvent {
     taca_KeyCode code;
     bool pressed;
+    // Auto repeat from holding the key down.
+    bool repeat;
+    // Bits from taca_KeyModifiers.
+    uint32_t modifiers;
 } taca_KeyEvent;
 
+typedef struct taca_MouseEvent {
+    // In physical pixels.
+    taca_Vec2f position;
+    // In lines unless deltaPixels.
+    taca_Vec2f delta;
+    taca_MouseButton button;
+    bool pressed;
+    bool deltaPixels;
+} taca_MouseEvent;
+
+typedef struct taca_TextEvent {
+    // Full utf8 size in bytes, which can exceed the buffer capacity.
+    uint32_t size;
+    // Byte range of the preedit cursor, or -1 if hidden.
+    int32_t cursorStart;
+    int32_t cursorEnd;
+    // In-progress IME composition to display but not yet insert.
+    bool preedit;
+} taca_TextEvent;
+
 // For now, call other functions to get details.
 typedef void (*taca_WindowListenCallback)(taca_WindowEventType type, void* userdata);
 
 // TODO Some init call giving a buffer to work with?
 
+// Button values range from 0 to 1 and axes from -1 to 1, with 0 for
+// disconnected pads.
+taca_EXPORT float taca_gamepadAxis(uint32_t pad, taca_GamepadAxis axis);
+taca_EXPORT float taca_gamepadButton(uint32_t pad, taca_GamepadButton button);
+taca_EXPORT bool taca_gamepadConnected(uint32_t pad);
+// Pads are numbered below this, though some might be disconnected.
+taca_EXPORT uint32_t taca_gamepadCount(void);
+taca_EXPORT taca_GamepadEvent taca_gamepadEvent(void);
 taca_EXPORT taca_KeyEvent taca_keyEvent(void);
+taca_EXPORT taca_MouseEvent taca_mouseEvent(void);
+// Copies as much utf8 text as fits in the buffer, without a null terminator.
+taca_EXPORT taca_TextEvent taca_textEvent(char* buffer, uint32_t capacity);
 taca_EXPORT taca_Vec2 taca_windowInnerSize(void);
 // TODO Use exported function and just register userdata here!!
 taca_EXPORT void taca_windowListen(taca_WindowListenCallback callback, void* userdata);
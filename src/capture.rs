@@ -0,0 +1,230 @@
+/// Writes each presented offscreen frame as a numbered png.
+pub struct FrameCapture {
+    pub count: u32,
+    pub dir: PathBuf,
+}
+
+impl FrameCapture {
+    pub fn new(dir: PathBuf) -> FrameCapture {
+        FrameCapture { count: 0, dir }
+    }
+}
+
+/// Tightly packed rgba8 pixels.
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+pub fn capture_frame(system: &mut System) -> Result<()> {
+    let frame = read_offscreen_frame(system)?;
+    let capture = system.capture.as_mut().unwrap();
+    let path = capture.dir.join(format!("frame-{:05}.png", capture.count));
+    write_png(&path, &frame).with_context(|| format!("Can't write {}", path.display()))?;
+    capture.count += 1;
+    Ok(())
+}
+
+// Texture to buffer copies need rows aligned to this.
+const COPY_BYTES_PER_ROW_ALIGNMENT: u32 = 256;
+
+pub fn read_offscreen_frame(system: &System) -> Result<Frame> {
+    let offscreen = match &system.offscreen {
+        Some(offscreen) if !offscreen.texture.0.is_null() && !system.device.0.is_null() => {
+            offscreen
+        }
+        Some(_) => bail!("No frame to read back before the app presents"),
+        None => bail!("Only offscreen frames can be read back"),
+    };
+    let width = offscreen.size.width;
+    let height = offscreen.size.height;
+    let swap_red_blue = match offscreen.format {
+        native::WGPUTextureFormat_RGBA8Unorm | native::WGPUTextureFormat_RGBA8UnormSrgb => false,
+        native::WGPUTextureFormat_BGRA8Unorm | native::WGPUTextureFormat_BGRA8UnormSrgb => true,
+        // The app picks the swap chain format, so it might be anything.
+        format => bail!("Can't read back frames of texture format: {format}"),
+    };
+    let row_size = 4 * width;
+    let bytes_per_row =
+        row_size.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT;
+    let buffer_size = bytes_per_row as u64 * height as u64;
+    unsafe {
+        let buffer = wgpu_native::device::wgpuDeviceCreateBuffer(
+            system.device.0,
+            Some(&native::WGPUBufferDescriptor {
+                nextInChain: null(),
+                label: null(),
+                usage: native::WGPUBufferUsage_CopyDst | native::WGPUBufferUsage_MapRead,
+                size: buffer_size,
+                mappedAtCreation: false,
+            }),
+        );
+        let encoder = wgpu_native::device::wgpuDeviceCreateCommandEncoder(
+            system.device.0,
+            Some(&native::WGPUCommandEncoderDescriptor {
+                nextInChain: null(),
+                label: null(),
+            }),
+        );
+        wgpu_native::command::wgpuCommandEncoderCopyTextureToBuffer(
+            encoder,
+            Some(&native::WGPUImageCopyTexture {
+                nextInChain: null(),
+                texture: offscreen.texture.0,
+                mipLevel: 0,
+                origin: native::WGPUOrigin3D { x: 0, y: 0, z: 0 },
+                aspect: native::WGPUTextureAspect_All,
+            }),
+            Some(&native::WGPUImageCopyBuffer {
+                nextInChain: null(),
+                layout: native::WGPUTextureDataLayout {
+                    nextInChain: null(),
+                    offset: 0,
+                    bytesPerRow: bytes_per_row,
+                    rowsPerImage: height,
+                },
+                buffer,
+            }),
+            Some(&native::WGPUExtent3D {
+                width,
+                height,
+                depthOrArrayLayers: 1,
+            }),
+        );
+        let command_buffer = wgpu_native::command::wgpuCommandEncoderFinish(
+            encoder,
+            Some(&native::WGPUCommandBufferDescriptor {
+                nextInChain: null(),
+                label: null(),
+            }),
+        );
+        wgpu_native::device::wgpuQueueSubmit(system.queue.0, 1, &command_buffer);
+        extern "C" fn map_callback(
+            status: native::WGPUBufferMapAsyncStatus,
+            userdata: *mut std::os::raw::c_void,
+        ) {
+            unsafe { *(userdata as *mut native::WGPUBufferMapAsyncStatus) = status };
+        }
+        let buffer_size = buffer_size as usize;
+        let mut status = native::WGPUBufferMapAsyncStatus_Unknown;
+        wgpu_native::device::wgpuBufferMapAsync(
+            buffer,
+            native::WGPUMapMode_Read,
+            0,
+            buffer_size,
+            Some(map_callback),
+            &mut status as *mut native::WGPUBufferMapAsyncStatus as *mut std::os::raw::c_void,
+        );
+        // Waiting also runs the map callback.
+        wgpu_native::device::wgpuDevicePoll(system.device.0, true, None);
+        let mapped = match status {
+            native::WGPUBufferMapAsyncStatus_Success => {
+                wgpu_native::device::wgpuBufferGetConstMappedRange(buffer, 0, buffer_size)
+            }
+            _ => null(),
+        };
+        if mapped.is_null() {
+            wgpu_native::device::wgpuBufferDrop(buffer);
+            bail!("Can't map frame for reading, with status: {status}");
+        }
+        let mapped = std::slice::from_raw_parts(mapped as *const u8, buffer_size);
+        let mut data = Vec::with_capacity(row_size as usize * height as usize);
+        for row in mapped.chunks(bytes_per_row as usize) {
+            data.extend_from_slice(&row[..row_size as usize]);
+        }
+        wgpu_native::device::wgpuBufferUnmap(buffer);
+        wgpu_native::device::wgpuBufferDrop(buffer);
+        if swap_red_blue {
+            for pixel in data.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        Ok(Frame {
+            width,
+            height,
+            data,
+        })
+    }
+}
+
+pub fn write_png(path: &Path, frame: &Frame) -> Result<()> {
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), frame.width, frame.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&frame.data)?;
+    Ok(())
+}
+
+pub fn read_png(path: &Path) -> Result<Frame> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    buffer.truncate(info.buffer_size());
+    let data = match info.color_type {
+        png::ColorType::Rgba => buffer,
+        png::ColorType::Rgb => buffer
+            .chunks(3)
+            .flat_map(|it| [it[0], it[1], it[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buffer
+            .chunks(2)
+            .flat_map(|it| [it[0], it[0], it[0], it[1]])
+            .collect(),
+        png::ColorType::Grayscale => buffer.iter().flat_map(|&it| [it, it, it, 255]).collect(),
+        png::ColorType::Indexed => bail!("Unexpanded indexed png: {}", path.display()),
+    };
+    Ok(Frame {
+        width: info.width,
+        height: info.height,
+        data,
+    })
+}
+
+use crate::system::System;
+use anyhow::{bail, Context, Result};
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    ptr::null,
+};
+use wgpu_native::native;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_round_trip() {
+        let frame = Frame {
+            width: 2,
+            height: 2,
+            data: vec![
+                255, 0, 0, 255, 0, 255, 0, 128, //
+                0, 0, 255, 0, 1, 2, 3, 4,
+            ],
+        };
+        let path = std::env::temp_dir().join(format!("taca-{}.png", std::process::id()));
+        write_png(&path, &frame).unwrap();
+        let read = read_png(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((read.width, read.height), (2, 2));
+        assert_eq!(read.data, frame.data);
+    }
+
+    #[test]
+    fn png_missing() {
+        assert!(read_png(Path::new("no/such/frame.png")).is_err());
+        let frame = Frame {
+            width: 1,
+            height: 1,
+            data: vec![0; 4],
+        };
+        assert!(write_png(Path::new("no/such/dir/frame.png"), &frame).is_err());
+    }
+}
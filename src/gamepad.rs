@@ -0,0 +1,379 @@
+/// Supplies gamepad changes, so tests can inject their own in place of real
+/// devices. Sources stay on the event loop thread rather than in the System.
+pub trait GamepadSource {
+    fn poll(&mut self) -> Option<GamepadEvent>;
+}
+
+// Follows the w3c standard gamepad layout.
+#[derive(Debug, Hash, Ord, PartialOrd, PartialEq, Eq, Clone, Copy)]
+#[repr(u32)]
+pub enum GamepadButton {
+    South = 0,
+    East = 1,
+    West = 2,
+    North = 3,
+    LeftBumper = 4,
+    RightBumper = 5,
+    LeftTrigger = 6,
+    RightTrigger = 7,
+    Select = 8,
+    Start = 9,
+    LeftStick = 10,
+    RightStick = 11,
+    DPadUp = 12,
+    DPadDown = 13,
+    DPadLeft = 14,
+    DPadRight = 15,
+    Mode = 16,
+}
+
+const BUTTON_COUNT: usize = 17;
+
+impl GamepadButton {
+    pub fn from_name(name: &str) -> Option<GamepadButton> {
+        match name {
+            "South" => Some(GamepadButton::South),
+            "East" => Some(GamepadButton::East),
+            "West" => Some(GamepadButton::West),
+            "North" => Some(GamepadButton::North),
+            "LeftBumper" => Some(GamepadButton::LeftBumper),
+            "RightBumper" => Some(GamepadButton::RightBumper),
+            "LeftTrigger" => Some(GamepadButton::LeftTrigger),
+            "RightTrigger" => Some(GamepadButton::RightTrigger),
+            "Select" => Some(GamepadButton::Select),
+            "Start" => Some(GamepadButton::Start),
+            "LeftStick" => Some(GamepadButton::LeftStick),
+            "RightStick" => Some(GamepadButton::RightStick),
+            "DPadUp" => Some(GamepadButton::DPadUp),
+            "DPadDown" => Some(GamepadButton::DPadDown),
+            "DPadLeft" => Some(GamepadButton::DPadLeft),
+            "DPadRight" => Some(GamepadButton::DPadRight),
+            "Mode" => Some(GamepadButton::Mode),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Hash, Ord, PartialOrd, PartialEq, Eq, Clone, Copy)]
+#[repr(u32)]
+pub enum GamepadAxis {
+    LeftX = 0,
+    LeftY = 1,
+    RightX = 2,
+    RightY = 3,
+}
+
+const AXIS_COUNT: usize = 4;
+
+/// Keeps scripted pad indices from growing state without bound.
+pub const MAX_GAMEPADS: u32 = 16;
+
+impl GamepadAxis {
+    pub fn from_name(name: &str) -> Option<GamepadAxis> {
+        match name {
+            "LeftX" => Some(GamepadAxis::LeftX),
+            "LeftY" => Some(GamepadAxis::LeftY),
+            "RightX" => Some(GamepadAxis::RightX),
+            "RightY" => Some(GamepadAxis::RightY),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Hash, Ord, PartialOrd, PartialEq, Eq, Clone, Copy)]
+#[repr(u32)]
+pub enum GamepadEventType {
+    Connect = 1,
+    Disconnect = 2,
+    Button = 3,
+    Axis = 4,
+}
+
+/// A change to one pad, where index is the button or axis.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct GamepadEvent {
+    pub pad: u32,
+    pub event_type: GamepadEventType,
+    pub index: u32,
+    pub value: f32,
+}
+
+#[derive(Clone, Default)]
+struct GamepadState {
+    axes: [f32; AXIS_COUNT],
+    buttons: [f32; BUTTON_COUNT],
+    connected: bool,
+}
+
+#[derive(Default)]
+pub struct Gamepads {
+    pub event: Option<GamepadEvent>,
+    pads: Vec<GamepadState>,
+}
+
+impl Gamepads {
+    pub fn apply(&mut self, event: GamepadEvent) {
+        let pad = event.pad as usize;
+        if pad >= self.pads.len() {
+            self.pads.resize(pad + 1, Default::default());
+        }
+        let state = &mut self.pads[pad];
+        match event.event_type {
+            GamepadEventType::Connect => {
+                *state = GamepadState {
+                    connected: true,
+                    ..Default::default()
+                }
+            }
+            GamepadEventType::Disconnect => *state = Default::default(),
+            GamepadEventType::Button => state.buttons[event.index as usize] = event.value,
+            GamepadEventType::Axis => state.axes[event.index as usize] = event.value,
+        }
+        self.event = Some(event);
+    }
+
+    fn pad(&self, pad: u32) -> Option<&GamepadState> {
+        self.pads.get(pad as usize).filter(|state| state.connected)
+    }
+}
+
+/// Sends an event to the app for each pending change from the source.
+pub fn poll_gamepads(
+    store: &mut Store,
+    env: &FunctionEnv<System>,
+    source: &mut dyn GamepadSource,
+) -> Result<(), RuntimeError> {
+    while let Some(event) = source.poll() {
+        send_gamepad_event(store, env, event)?;
+    }
+    Ok(())
+}
+
+pub fn send_gamepad_event(
+    store: &mut Store,
+    env: &FunctionEnv<System>,
+    event: GamepadEvent,
+) -> Result<(), RuntimeError> {
+    env.as_mut(store).gamepads.apply(event);
+    send_event(store, env, WindowEventType::Gamepad)
+}
+
+struct GilrsSource {
+    gilrs: Gilrs,
+    /// Connects for pads already attached at startup, which gilrs doesn't
+    /// report as events.
+    pending: VecDeque<GamepadEvent>,
+}
+
+impl GamepadSource for GilrsSource {
+    fn poll(&mut self) -> Option<GamepadEvent> {
+        if let Some(event) = self.pending.pop_front() {
+            return Some(event);
+        }
+        while let Some(gilrs::Event { id, event, .. }) = self.gilrs.next_event() {
+            let (event_type, index, value) = match event {
+                EventType::Connected => (GamepadEventType::Connect, 0, 0.0),
+                EventType::Disconnected => (GamepadEventType::Disconnect, 0, 0.0),
+                EventType::ButtonChanged(button, value, _) => match convert_button(button) {
+                    Some(button) => (GamepadEventType::Button, button as u32, value),
+                    None => continue,
+                },
+                EventType::AxisChanged(axis, value, _) => match convert_axis(axis) {
+                    Some(axis) => (GamepadEventType::Axis, axis as u32, value),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            return Some(GamepadEvent {
+                pad: usize::from(id) as u32,
+                event_type,
+                index,
+                value,
+            });
+        }
+        None
+    }
+}
+
+/// Real devices through gilrs, which reads evdev on Linux, so virtual uinput
+/// pads also work.
+pub fn gilrs_source() -> Option<Box<dyn GamepadSource>> {
+    match Gilrs::new() {
+        Ok(gilrs) => {
+            let pending = gilrs
+                .gamepads()
+                .map(|(id, _)| GamepadEvent {
+                    pad: usize::from(id) as u32,
+                    event_type: GamepadEventType::Connect,
+                    index: 0,
+                    value: 0.0,
+                })
+                .collect();
+            Some(Box::new(GilrsSource { gilrs, pending }))
+        }
+        Err(err) => {
+            eprintln!("No gamepad support: {err}");
+            None
+        }
+    }
+}
+
+fn convert_axis(axis: gilrs::Axis) -> Option<GamepadAxis> {
+    match axis {
+        gilrs::Axis::LeftStickX => Some(GamepadAxis::LeftX),
+        gilrs::Axis::LeftStickY => Some(GamepadAxis::LeftY),
+        gilrs::Axis::RightStickX => Some(GamepadAxis::RightX),
+        gilrs::Axis::RightStickY => Some(GamepadAxis::RightY),
+        _ => None,
+    }
+}
+
+fn convert_button(button: gilrs::Button) -> Option<GamepadButton> {
+    // Gilrs calls bumpers triggers and triggers triggers 2.
+    match button {
+        gilrs::Button::South => Some(GamepadButton::South),
+        gilrs::Button::East => Some(GamepadButton::East),
+        gilrs::Button::West => Some(GamepadButton::West),
+        gilrs::Button::North => Some(GamepadButton::North),
+        gilrs::Button::LeftTrigger => Some(GamepadButton::LeftBumper),
+        gilrs::Button::RightTrigger => Some(GamepadButton::RightBumper),
+        gilrs::Button::LeftTrigger2 => Some(GamepadButton::LeftTrigger),
+        gilrs::Button::RightTrigger2 => Some(GamepadButton::RightTrigger),
+        gilrs::Button::Select => Some(GamepadButton::Select),
+        gilrs::Button::Start => Some(GamepadButton::Start),
+        gilrs::Button::LeftThumb => Some(GamepadButton::LeftStick),
+        gilrs::Button::RightThumb => Some(GamepadButton::RightStick),
+        gilrs::Button::DPadUp => Some(GamepadButton::DPadUp),
+        gilrs::Button::DPadDown => Some(GamepadButton::DPadDown),
+        gilrs::Button::DPadLeft => Some(GamepadButton::DPadLeft),
+        gilrs::Button::DPadRight => Some(GamepadButton::DPadRight),
+        gilrs::Button::Mode => Some(GamepadButton::Mode),
+        _ => None,
+    }
+}
+
+pub fn taca_gamepad_axis(env: FunctionEnvMut<System>, pad: u32, axis: u32) -> f32 {
+    match env.data().gamepads.pad(pad) {
+        Some(state) => state.axes.get(axis as usize).copied().unwrap_or(0.0),
+        None => 0.0,
+    }
+}
+
+pub fn taca_gamepad_button(env: FunctionEnvMut<System>, pad: u32, button: u32) -> f32 {
+    match env.data().gamepads.pad(pad) {
+        Some(state) => state.buttons.get(button as usize).copied().unwrap_or(0.0),
+        None => 0.0,
+    }
+}
+
+pub fn taca_gamepad_connected(env: FunctionEnvMut<System>, pad: u32) -> u32 {
+    env.data().gamepads.pad(pad).is_some() as u32
+}
+
+pub fn taca_gamepad_count(env: FunctionEnvMut<System>) -> u32 {
+    env.data().gamepads.pads.len() as u32
+}
+
+#[derive(Copy, Clone, Debug, ValueType)]
+#[repr(C)]
+struct WasmGamepadEvent {
+    pad: u32,
+    event_type: u32,
+    index: u32,
+    value: f32,
+}
+
+pub fn taca_gamepad_event(mut env: FunctionEnvMut<System>, result: u32) {
+    let (system, mut store) = env.data_and_store_mut();
+    let view = system.memory.as_ref().unwrap().view(&mut store);
+    let gamepad_event = match system.gamepads.event {
+        Some(event) => WasmGamepadEvent {
+            pad: event.pad,
+            event_type: event.event_type as u32,
+            index: event.index,
+            value: event.value,
+        },
+        None => WasmGamepadEvent {
+            pad: 0,
+            event_type: 0,
+            index: 0,
+            value: 0.0,
+        },
+    };
+    WasmRef::<WasmGamepadEvent>::new(&view, result as u64)
+        .write(gamepad_event)
+        .unwrap();
+}
+
+use crate::{
+    system::System,
+    window::{send_event, WindowEventType},
+};
+use gilrs::{EventType, Gilrs};
+use std::collections::VecDeque;
+use wasmer::{FunctionEnv, FunctionEnvMut, RuntimeError, Store, ValueType, WasmRef};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmer::{imports, Instance, Module};
+
+    struct MockSource(VecDeque<GamepadEvent>);
+
+    impl GamepadSource for MockSource {
+        fn poll(&mut self) -> Option<GamepadEvent> {
+            self.0.pop_front()
+        }
+    }
+
+    fn event(pad: u32, event_type: GamepadEventType, index: u32, value: f32) -> GamepadEvent {
+        GamepadEvent {
+            pad,
+            event_type,
+            index,
+            value,
+        }
+    }
+
+    #[test]
+    fn poll_mock_source() {
+        let mut store = Store::default();
+        // Just counts events.
+        let module = Module::new(
+            &store,
+            r#"(module
+                (global $count (export "count") (mut i32) (i32.const 0))
+                (func (export "listen") (param i32 i32)
+                    (global.set $count (i32.add (global.get $count) (i32.const 1)))))"#,
+        )
+        .unwrap();
+        let instance = Instance::new(&mut store, &module, &imports! {}).unwrap();
+        let env = FunctionEnv::new(&mut store, System::default());
+        env.as_mut(&mut store).window_listen =
+            Some(instance.exports.get_function("listen").unwrap().clone());
+        let south = GamepadButton::South as u32;
+        let left_x = GamepadAxis::LeftX as u32;
+        let mut source = MockSource(VecDeque::from([
+            event(1, GamepadEventType::Connect, 0, 0.0),
+            event(1, GamepadEventType::Button, south, 1.0),
+            event(1, GamepadEventType::Axis, left_x, -0.5),
+            event(2, GamepadEventType::Connect, 0, 0.0),
+            event(2, GamepadEventType::Disconnect, 0, 0.0),
+        ]));
+        poll_gamepads(&mut store, &env, &mut source).unwrap();
+        assert!(source.0.is_empty());
+        let count = instance.exports.get_global("count").unwrap();
+        assert_eq!(count.get(&mut store).unwrap_i32(), 5);
+        let gamepads = &env.as_ref(&store).gamepads;
+        assert_eq!(
+            gamepads.event,
+            Some(event(2, GamepadEventType::Disconnect, 0, 0.0))
+        );
+        assert!(gamepads.pad(0).is_none());
+        assert!(gamepads.pad(2).is_none());
+        let pad = gamepads.pad(1).unwrap();
+        assert_eq!(pad.buttons[south as usize], 1.0);
+        assert_eq!(pad.axes[left_x as usize], -0.5);
+        assert_eq!(gamepads.pads.len(), 3);
+    }
+}
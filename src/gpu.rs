@@ -1,23 +1,32 @@
 use std::{
-    ffi::CString,
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    ffi::{CStr, CString},
+    ops::Range,
     ptr::{null, null_mut},
     sync::{Arc, Mutex},
 };
 
 use crate::{
-    system::{System, WGPUBindGroup, WGPUBuffer, WGPURenderPipeline, WGPUTexture, WGPUTextureView},
+    system::{
+        System, WGPUBindGroup, WGPUBindGroupLayout, WGPUBuffer, WGPUComputePipeline,
+        WGPUPipelineLayout, WGPURenderPipeline, WGPUSampler, WGPUTexture, WGPUTextureView,
+    },
     webgpu::{
-        read_cstring, wgpu_adapter_ensure_device_simple, wgpu_adapter_get_limits_simple,
-        wgpu_device_create_shader_module_simple, wgpu_device_ensure_command_encoder_simple,
+        read_blend_state, read_cstring, wgpu_adapter_ensure_device_simple,
+        wgpu_adapter_get_limits_simple, wgpu_device_create_shader_module_simple,
+        wgpu_device_create_swap_chain_simple, wgpu_device_ensure_command_encoder_simple,
         wgpu_device_ensure_queue_simple, wgpu_device_ensure_uncaptured_error_callback_simple,
         wgpu_ensure_command_encoder_finish_simple, wgpu_ensure_instance_simple,
         wgpu_ensure_queue_submit_simple, wgpu_instance_ensure_adapter_simple,
         wgpu_instance_ensure_surface_simple, wgpu_surface_get_preferred_format_simple,
-        wgpu_swap_chain_drop_simple, WasmWGPUVertexBufferLayout,
+        wgpu_swap_chain_drop_simple, wgpu_swap_chain_get_current_texture_view_simple,
+        wgpu_swap_chain_present_simple, WasmWGPUColor, WasmWGPUSamplerDescriptor,
+        WasmWGPUVertexBufferLayout,
     },
     window::WindowEventType,
 };
-use wasmer::{FunctionEnvMut, ValueType, WasmPtr};
+use wasmer::{FunctionEnvMut, RuntimeError, ValueType, WasmPtr};
 use wgpu_native::{
     device::{wgpuBufferDrop, wgpuDeviceDrop, wgpuRenderPipelineDrop},
     native,
@@ -29,7 +38,17 @@ pub struct GpuBuffer {
     data: Vec<u8>,
     detail: GpuBufferDetail,
     size: usize,
-    written: bool,
+    /// Bytes changed since the last upload.
+    dirty: Option<Range<usize>>,
+}
+
+impl GpuBuffer {
+    fn mark_dirty(&mut self, range: Range<usize>) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(range.start)..dirty.end.max(range.end),
+            None => range,
+        });
+    }
 }
 
 #[derive(Debug)]
@@ -38,6 +57,10 @@ pub enum GpuBufferDetail {
         format: native::WGPUIndexFormat,
         vertex: Arc<Mutex<GpuBuffer>>,
     },
+    Storage {
+        binding: u32,
+        read_only: bool,
+    },
     Uniform {
         binding: u32,
     },
@@ -51,8 +74,14 @@ unsafe impl Send for WGPUTextureDescriptor {}
 
 struct GpuTexture {
     binding: u32,
-    data: Vec<u8>,
     descriptor: WGPUTextureDescriptor,
+    /// Rebuilds later levels from the first whenever it changes.
+    generate_mips: bool,
+    /// Data for each mip level, kept for uploading to new devices.
+    levels: Vec<Vec<u8>>,
+    /// Writable from compute shaders, though still sampled when rendering.
+    storage: bool,
+    texture: WGPUTexture,
     texture_view: Option<WGPUTextureView>,
 }
 
@@ -65,6 +94,17 @@ struct WasmGpuTextureInfo {
     height: u32,
 }
 
+#[derive(Copy, Clone, Debug, ValueType)]
+#[repr(C)]
+struct WasmGpuTextureDetail {
+    format: native::WGPUTextureFormat,
+    binding: u32,
+    width: u32,
+    height: u32,
+    mip_level_count: u32,
+    generate_mips: bool,
+}
+
 #[derive(Debug)]
 pub struct WgpuVertexBufferLayout {
     array_stride: u64,
@@ -80,24 +120,328 @@ pub struct WgpuVertexBufferLayout {
     slot: u32,
 }
 
+impl WgpuVertexBufferLayout {
+    fn key(&self) -> VertexLayoutKey {
+        let attributes = self
+            .attributes
+            .iter()
+            .map(|it| (it.format, it.offset, it.shaderLocation))
+            .collect();
+        VertexLayoutKey(self.array_stride, self.step_mode, attributes)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct GpuPipeline {
+    blend: Option<native::WGPUBlendState>,
+    cull_mode: native::WGPUCullMode,
+    depth_compare: native::WGPUCompareFunction,
+    depth_write: bool,
+    fragment_entry: CString,
+    front_face: native::WGPUFrontFace,
+    shader: usize,
+    topology: native::WGPUPrimitiveTopology,
+    vertex_entry: CString,
+    write_mask: native::WGPUColorWriteMaskFlags,
+}
+
+impl Default for GpuPipeline {
+    /// Used until the app picks a pipeline, for apps with a single shader.
+    fn default() -> Self {
+        GpuPipeline {
+            blend: None,
+            cull_mode: native::WGPUCullMode_None,
+            depth_compare: native::WGPUCompareFunction_Less,
+            depth_write: true,
+            fragment_entry: CString::new("fs_main").unwrap(),
+            front_face: native::WGPUFrontFace_CCW,
+            shader: 0,
+            topology: native::WGPUPrimitiveTopology_TriangleList,
+            vertex_entry: CString::new("vs_main").unwrap(),
+            write_mask: native::WGPUColorWriteMask_All,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, ValueType)]
+#[repr(C)]
+struct WasmGpuPipelineInfo {
+    vertex_entry: u32,
+    fragment_entry: u32,
+    topology: u32,
+    cull_mode: native::WGPUCullMode,
+    front_face: native::WGPUFrontFace,
+    depth_compare: native::WGPUCompareFunction,
+    depth_read_only: bool,
+    blend: u32, // WGPUBlendState const *
+    write_mask: native::WGPUColorWriteMaskFlags,
+    blend_mode: u32,
+    use_write_mask: bool,
+}
+
+#[derive(Debug, Default, Hash, Ord, PartialOrd, PartialEq, Eq, Clone, Copy)]
+#[repr(u32)]
+enum GpuBlendMode {
+    #[default]
+    Default = 0,
+    Opaque = 1,
+    Alpha = 2,
+    Premultiplied = 3,
+    Additive = 4,
+    Multiply = 5,
+}
+
+impl GpuBlendMode {
+    fn from_u32(mode: u32) -> Result<GpuBlendMode, RuntimeError> {
+        use GpuBlendMode::*;
+        Ok(match mode {
+            0 => Default,
+            1 => Opaque,
+            2 => Alpha,
+            3 => Premultiplied,
+            4 => Additive,
+            5 => Multiply,
+            _ => return Err(RuntimeError::new(format!("Invalid blend mode: {mode}"))),
+        })
+    }
+
+    /// None for opaque, and Default has no state of its own.
+    fn blend_state(self) -> Option<native::WGPUBlendState> {
+        let component = |src, dst| native::WGPUBlendComponent {
+            operation: native::WGPUBlendOperation_Add,
+            srcFactor: src,
+            dstFactor: dst,
+        };
+        let (color, alpha) = match self {
+            GpuBlendMode::Default | GpuBlendMode::Opaque => return None,
+            GpuBlendMode::Alpha => (
+                component(
+                    native::WGPUBlendFactor_SrcAlpha,
+                    native::WGPUBlendFactor_OneMinusSrcAlpha,
+                ),
+                component(
+                    native::WGPUBlendFactor_One,
+                    native::WGPUBlendFactor_OneMinusSrcAlpha,
+                ),
+            ),
+            GpuBlendMode::Premultiplied => {
+                let both = component(
+                    native::WGPUBlendFactor_One,
+                    native::WGPUBlendFactor_OneMinusSrcAlpha,
+                );
+                (both, both)
+            }
+            GpuBlendMode::Additive => {
+                let both = component(native::WGPUBlendFactor_One, native::WGPUBlendFactor_One);
+                (both, both)
+            }
+            GpuBlendMode::Multiply => (
+                component(native::WGPUBlendFactor_Dst, native::WGPUBlendFactor_Zero),
+                // Leave the destination alpha alone.
+                component(native::WGPUBlendFactor_Zero, native::WGPUBlendFactor_One),
+            ),
+        };
+        Some(native::WGPUBlendState { color, alpha })
+    }
+}
+
+// Indexed by taca_gpu_Topology, ordered so zero is the default.
+const TOPOLOGIES: [native::WGPUPrimitiveTopology; 5] = [
+    native::WGPUPrimitiveTopology_TriangleList,
+    native::WGPUPrimitiveTopology_TriangleStrip,
+    native::WGPUPrimitiveTopology_LineList,
+    native::WGPUPrimitiveTopology_LineStrip,
+    native::WGPUPrimitiveTopology_PointList,
+];
+
+fn topology(value: u32) -> Result<native::WGPUPrimitiveTopology, RuntimeError> {
+    TOPOLOGIES
+        .get(value as usize)
+        .copied()
+        .ok_or_else(|| RuntimeError::new(format!("Invalid topology: {value}")))
+}
+
+/// Native pipelines get compiled per combination actually drawn.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct PipelineKey {
+    blend_mode: GpuBlendMode,
+    /// Undefined when the pass has no color attachment.
+    color_format: native::WGPUTextureFormat,
+    /// Undefined when the pass has no depth attachment.
+    depth_format: native::WGPUTextureFormat,
+    /// Vertex then any instance layout, so buffers that match share.
+    layouts: Vec<VertexLayoutKey>,
+    pipeline: u32,
+    /// Undefined except for strips drawn from index buffers.
+    strip_index_format: native::WGPUIndexFormat,
+}
+
+/// Stride, step mode, and format, offset, and location of each attribute.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct VertexLayoutKey(u64, native::WGPUVertexStepMode, Vec<(u32, u64, u32)>);
+
+/// Texture handles drawn to, where both zero means the frame.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+struct GpuTarget {
+    color: u32,
+    depth: u32,
+}
+
+/// Stand-ins for textures that can't be sampled while attached to the pass.
+#[derive(Default)]
+struct GpuDummyTextures {
+    color: WGPUTexture,
+    color_view: WGPUTextureView,
+    depth: WGPUTexture,
+    depth_view: WGPUTextureView,
+}
+
+struct GpuShader {
+    /// Raw api shader handle, or 0 if not yet created on the device.
+    module: u32,
+    wgsl: CString,
+}
+
+#[derive(Clone, Copy)]
+struct GpuFrameOptions {
+    clear_color: native::WGPUColor,
+    clear_depth: f32,
+    load_color: bool,
+    load_depth: bool,
+}
+
+impl Default for GpuFrameOptions {
+    fn default() -> Self {
+        GpuFrameOptions {
+            clear_color: native::WGPUColor {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+                a: 0.0,
+            },
+            clear_depth: 1.0,
+            load_color: false,
+            load_depth: false,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, ValueType)]
+#[repr(C)]
+struct WasmGpuFrameOptions {
+    clear_color: WasmWGPUColor,
+    clear_depth: f32,
+    load_color: bool,
+    load_depth: bool,
+}
+
+/// Copies the persistent frame texture to the swap chain.
+#[derive(Default)]
+struct GpuBlit {
+    bind_group: WGPUBindGroup,
+    bind_group_layout: WGPUBindGroupLayout,
+    /// Whether the current frame renders to the frame texture.
+    needed: bool,
+    pipeline: WGPURenderPipeline,
+    /// Raw api shader handle, kept across resizes, or 0 until created.
+    shader: u32,
+}
+
+const BLIT_WGSL: &str = "
+@group(0) @binding(0) var frame: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // One triangle covering the screen.
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    return textureLoad(frame, vec2<i32>(position.xy), 0);
+}
+\0";
+
+struct GpuSampler {
+    binding: u32,
+    descriptor: WasmWGPUSamplerDescriptor,
+    sampler: WGPUSampler,
+}
+
+impl GpuSampler {
+    fn binding_type(&self) -> native::WGPUSamplerBindingType {
+        let descriptor = &self.descriptor;
+        if descriptor.compare != native::WGPUCompareFunction_Undefined {
+            native::WGPUSamplerBindingType_Comparison
+        } else if descriptor.mag_filter == native::WGPUFilterMode_Linear
+            || descriptor.min_filter == native::WGPUFilterMode_Linear
+            || descriptor.mipmap_filter == native::WGPUMipmapFilterMode_Linear
+        {
+            native::WGPUSamplerBindingType_Filtering
+        } else {
+            native::WGPUSamplerBindingType_NonFiltering
+        }
+    }
+}
+
+struct GpuCompute {
+    entry: CString,
+    /// Built on first dispatch.
+    pipeline: WGPUComputePipeline,
+    shader: usize,
+}
+
 #[derive(Default)]
 pub struct SimpleGpu {
     bind_group: WGPUBindGroup,
+    bind_group_layout: WGPUBindGroupLayout,
+    /// Overrides the pipeline blend unless Default.
+    blend_mode: GpuBlendMode,
     buffers: Vec<Arc<Mutex<GpuBuffer>>>,
+    blit: GpuBlit,
+    /// Current compute handle, or 0 for the first.
+    compute: u32,
+    /// Compute binds storage textures as writable, unlike render.
+    compute_bind_group: WGPUBindGroup,
+    compute_bind_group_layout: WGPUBindGroupLayout,
+    compute_pipeline_layout: WGPUPipelineLayout,
+    computes: Vec<GpuCompute>,
     depth_texture: WGPUTexture,
+    /// Zeroed depth can't be loaded meaningfully, so clear it first.
+    depth_texture_fresh: bool,
     depth_texture_view: WGPUTextureView,
-    pipeline: WGPURenderPipeline,
+    dummy_textures: GpuDummyTextures,
+    frame: GpuFrameOptions,
+    /// Render passes after others in the same frame load instead of clear.
+    frame_begun: bool,
+    /// Persists the previous frame for loading when the swap chain can't.
+    frame_texture: WGPUTexture,
+    frame_texture_fresh: bool,
+    frame_texture_view: WGPUTextureView,
+    /// Current pipeline handle, or 0 for default.
+    pipeline: u32,
+    pipeline_layout: WGPUPipelineLayout,
+    pipelines: Vec<GpuPipeline>,
+    render_pipelines: HashMap<PipelineKey, WGPURenderPipeline>,
     render_texture_view: WGPUTextureView,
-    shaders: Vec<CString>,
+    samplers: Vec<GpuSampler>,
+    shaders: Vec<GpuShader>,
+    target: GpuTarget,
+    /// Bind groups that swap in dummies for the target's own textures.
+    target_bind_groups: HashMap<GpuTarget, WGPUBindGroup>,
+    /// Target textures already drawn this frame, so later passes load them.
+    targets_begun: HashSet<u32>,
     textures: Vec<GpuTexture>,
 }
 
 pub fn gpu_window_listen(system: &mut System, event_type: WindowEventType) {
     if event_type == WindowEventType::Resize {
-        if !system.swap_chain.0.is_null() {
+        if system.has_swap_chain() {
             wgpu_swap_chain_drop_simple(system);
             ensure_swap_chain(system);
             reset_depth_texture(system);
+            drop_frame_texture(system);
         }
     }
 }
@@ -107,14 +451,157 @@ pub fn taca_gpu_buffer_write(mut env: FunctionEnvMut<System>, buffer: u32, data:
     let (system, store) = env.data_and_store_mut();
     let view = system.memory.as_ref().unwrap().view(&store);
     let mut buffer = system.gpu.buffers[buffer as usize - 1].lock().unwrap();
-    buffer.written = false;
+    let size = buffer.data.len();
+    buffer.mark_dirty(0..size);
     WasmPtr::<u8>::new(data)
-        .slice(&view, buffer.data.len() as u32)
+        .slice(&view, size as u32)
         .unwrap()
         .read_slice(buffer.data.as_mut_slice())
         .unwrap();
 }
 
+/// taca_gpu_bufferWriteRange
+pub fn taca_gpu_buffer_write_range(
+    mut env: FunctionEnvMut<System>,
+    buffer: u32,
+    offset: u32,
+    size: u32,
+    data: u32,
+) -> Result<(), RuntimeError> {
+    let (system, store) = env.data_and_store_mut();
+    let view = system.memory.as_ref().unwrap().view(&store);
+    let mut buffer = system.gpu.buffers[buffer as usize - 1].lock().unwrap();
+    let range = write_range(offset, size, buffer.data.len()).ok_or_else(|| {
+        RuntimeError::new(format!(
+            "Write of {size} at {offset} past buffer end: {}",
+            buffer.data.len()
+        ))
+    })?;
+    buffer.mark_dirty(range.clone());
+    WasmPtr::<u8>::new(data)
+        .slice(&view, size)
+        .unwrap()
+        .read_slice(&mut buffer.data[range])
+        .unwrap();
+    Ok(())
+}
+
+/// The range to write if it fits without overflow.
+fn write_range(offset: u32, size: u32, len: usize) -> Option<Range<usize>> {
+    let end = offset.checked_add(size)? as usize;
+    (end <= len).then_some(offset as usize..end)
+}
+
+/// taca_gpu_bufferResize
+pub fn taca_gpu_buffer_resize(mut env: FunctionEnvMut<System>, buffer: u32, size: u32) {
+    let system = env.data_mut();
+    let buffer = system.gpu.buffers[buffer as usize - 1].clone();
+    let gpu_written = {
+        let mut buffer = buffer.lock().unwrap();
+        buffer.data.resize(size as usize, 0);
+        // Keep pending writes that still fit.
+        let size = size as usize;
+        buffer.dirty = buffer
+            .dirty
+            .take()
+            .map(|dirty| dirty.start.min(size)..dirty.end.min(size))
+            .filter(|dirty| !dirty.is_empty());
+        let writable = matches!(
+            buffer.detail,
+            GpuBufferDetail::Storage {
+                read_only: false,
+                ..
+            }
+        );
+        writable && !buffer.buffer.0.is_null()
+    };
+    // Otherwise the size mismatch makes a new gpu buffer with everything
+    // uploaded, but shaders might have changed what's on the gpu.
+    if gpu_written {
+        resize_gpu_buffer(system, &buffer);
+    }
+}
+
+/// Copies what fits to a new gpu buffer. Contents still only come from the
+/// host if the device gets recreated, such as for new limits.
+fn resize_gpu_buffer(system: &mut System, buffer: &Arc<Mutex<GpuBuffer>>) {
+    // Copies can't happen during render passes, and later draws resume.
+    end_render_pass(system);
+    wgpu_device_ensure_command_encoder_simple(system);
+    {
+        let mut buffer = buffer.lock().unwrap();
+        let old = buffer.buffer.0;
+        // Queue writes run before the copy, so send pending writes to the old
+        // buffer for the copy to keep.
+        if let Some(dirty) = buffer.dirty.take() {
+            let end = dirty.end.min(buffer.size);
+            if dirty.start < end {
+                let (offset, data) = aligned_upload(&buffer.data, dirty.start..end);
+                unsafe {
+                    wgpu_native::device::wgpuQueueWriteBuffer(
+                        system.queue.0,
+                        old,
+                        offset as u64,
+                        data.as_ptr(),
+                        data.len(),
+                    );
+                }
+            }
+            if end < dirty.end {
+                buffer.mark_dirty(end.max(dirty.start)..dirty.end);
+            }
+        }
+        let new = create_gpu_buffer(system, &buffer);
+        // Copies need 4-byte alignment, which both buffers have from padding.
+        let copied = (buffer.size.min(buffer.data.len()) + 3) & !3;
+        unsafe {
+            wgpu_native::command::wgpuCommandEncoderCopyBufferToBuffer(
+                system.encoder.0,
+                old,
+                0,
+                new,
+                0,
+                copied as u64,
+            );
+            wgpuBufferDrop(old);
+        }
+        buffer.buffer.0 = new;
+        buffer.size = buffer.data.len();
+        if copied < buffer.size {
+            let size = buffer.size;
+            buffer.mark_dirty(copied..size);
+        }
+    }
+    if !system.gpu.bind_group.0.is_null() {
+        reset_bind_group(system);
+    }
+}
+
+fn create_gpu_buffer(system: &System, buffer: &GpuBuffer) -> native::WGPUBuffer {
+    unsafe {
+        wgpu_native::device::wgpuDeviceCreateBuffer(
+            system.device.0,
+            Some(&native::WGPUBufferDescriptor {
+                nextInChain: null(),
+                label: null(),
+                usage: native::WGPUBufferUsage_CopyDst
+                    | match buffer.detail {
+                        GpuBufferDetail::Index { .. } => native::WGPUBufferUsage_Index,
+                        // Copy source for keeping contents on resize.
+                        GpuBufferDetail::Storage { .. } => {
+                            native::WGPUBufferUsage_Storage | native::WGPUBufferUsage_CopySrc
+                        }
+                        GpuBufferDetail::Uniform { .. } => native::WGPUBufferUsage_Uniform,
+                        GpuBufferDetail::Vertex { .. } => native::WGPUBufferUsage_Vertex,
+                    },
+                // Padded for 4-byte aligned writes and copies.
+                size: (buffer.data.len() as u64 + 3) & !3,
+                mappedAtCreation: false,
+            }),
+        )
+    }
+}
+
 // From https://stackoverflow.com/a/68027744/2748187
 #[macro_export]
 macro_rules! extract_enum_value {
@@ -144,6 +631,17 @@ fn check_limits(system: &mut System) -> bool {
         .clone()
         .map(|it| it.lock().unwrap().data.len())
         .sum();
+    let storage_buffers = system
+        .gpu
+        .buffers
+        .iter()
+        .filter(|it| matches!(it.lock().unwrap().detail, GpuBufferDetail::Storage { .. }));
+    let storage_buffer_count = storage_buffers.clone().count();
+    let max_storage_buffer_binding_size: usize = storage_buffers
+        .clone()
+        .map(|it| it.lock().unwrap().data.len())
+        .max()
+        .unwrap_or(0);
     let vertex_buffers = system
         .gpu
         .buffers
@@ -191,7 +689,16 @@ fn check_limits(system: &mut System) -> bool {
             (ratio * max_uniform_buffer_binding_size as f64) as u64;
         any_change = true;
     }
+    if max_storage_buffer_binding_size > limits.maxStorageBufferBindingSize as usize {
+        limits.maxStorageBufferBindingSize =
+            (ratio * max_storage_buffer_binding_size as f64) as u64;
+        any_change = true;
+    }
     // Just set exactly those that seem likely to change less.
+    if storage_buffer_count > limits.maxStorageBuffersPerShaderStage as usize {
+        limits.maxStorageBuffersPerShaderStage = storage_buffer_count as u32;
+        any_change = true;
+    }
     if uniform_buffer_count > limits.maxUniformBuffersPerShaderStage as usize {
         limits.maxUniformBuffersPerShaderStage = uniform_buffer_count as u32;
         any_change = true;
@@ -243,51 +750,61 @@ fn check_limits(system: &mut System) -> bool {
 }
 
 fn update_buffers(system: &mut System, need_all: bool) {
+    let mut bound_recreated = false;
     for buffer in &system.gpu.buffers {
         let mut buffer = buffer.lock().unwrap();
         // TODO If previous size non-zero and less than current, reserve extra?
-        // TODO Does the full buffer size get seen no matter the latest write?
-        let needed =
-            need_all || buffer.size == 0 || buffer.data.len() > buffer.size || !buffer.written;
-        if !needed {
-            continue;
+        let recreate = buffer.buffer.0.is_null() || buffer.size != buffer.data.len();
+        let range = match need_all || recreate {
+            true => 0..buffer.data.len(),
+            false => match buffer.dirty.take() {
+                Some(range) => range,
+                None => continue,
+            },
+        };
+        buffer.dirty = None;
+        if recreate {
+            if !buffer.buffer.0.is_null() {
+                unsafe { wgpuBufferDrop(buffer.buffer.0) };
+            }
+            buffer.size = buffer.data.len();
+            buffer.buffer.0 = create_gpu_buffer(system, &buffer);
+            bound_recreated |= matches!(
+                buffer.detail,
+                GpuBufferDetail::Storage { .. } | GpuBufferDetail::Uniform { .. }
+            );
         }
-        if !buffer.buffer.0.is_null() && buffer.size != buffer.data.len() {
-            unsafe { wgpuBufferDrop(buffer.buffer.0) };
-            buffer.buffer.0 = null_mut();
+        if range.is_empty() {
+            continue;
         }
-        buffer.size = buffer.data.len();
+        let (offset, data) = aligned_upload(&buffer.data, range);
         unsafe {
-            if buffer.buffer.0.is_null() {
-                buffer.buffer.0 = wgpu_native::device::wgpuDeviceCreateBuffer(
-                    system.device.0,
-                    Some(&native::WGPUBufferDescriptor {
-                        nextInChain: null(),
-                        label: null(),
-                        usage: native::WGPUBufferUsage_CopyDst
-                            | match buffer.detail {
-                                GpuBufferDetail::Index { .. } => native::WGPUBufferUsage_Index,
-                                GpuBufferDetail::Uniform { .. } => native::WGPUBufferUsage_Uniform,
-                                GpuBufferDetail::Vertex { .. } => native::WGPUBufferUsage_Vertex,
-                            },
-                        size: buffer.size as u64,
-                        mappedAtCreation: false,
-                    }),
-                );
-            }
-            // if let GpuBufferDetail::Vertex { .. } = buffer.detail {
-            //     dbg!(&buffer);
-            // }
             wgpu_native::device::wgpuQueueWriteBuffer(
                 system.queue.0,
                 buffer.buffer.0,
-                0,
-                buffer.data.as_ptr(),
-                buffer.data.len(),
+                offset as u64,
+                data.as_ptr(),
+                data.len(),
             );
-            buffer.written = true;
         }
     }
+    // Full changes rebuild the layout and bind group after this anyway.
+    if bound_recreated && !need_all && !system.gpu.bind_group.0.is_null() {
+        reset_bind_group(system);
+    }
+}
+
+/// Widens the range to the 4-byte alignment that queue writes need, padding
+/// past the end of the data like the gpu buffer does.
+fn aligned_upload(data: &[u8], range: Range<usize>) -> (usize, Cow<[u8]>) {
+    let start = range.start & !3;
+    let end = (range.end + 3) & !3;
+    if end <= data.len() {
+        return (start, Cow::Borrowed(&data[start..end]));
+    }
+    let mut padded = data[start..].to_vec();
+    padded.resize(end - start, 0);
+    (start, Cow::Owned(padded))
 }
 
 fn update_textures(system: &mut System, need_all: bool) {
@@ -298,46 +815,25 @@ fn update_textures(system: &mut System, need_all: bool) {
         }
         if let Some(texture_view) = &texture.texture_view {
             // TODO Dispose.
-            // TODO Need the original texture for disposing that also?
             let _ = texture_view;
         }
         let descriptor = &texture.descriptor.0;
-        let device_texture = unsafe {
+        texture.texture.0 = unsafe {
             wgpu_native::device::wgpuDeviceCreateTexture(system.device.0, Some(descriptor))
         };
-        let size = descriptor.size;
-        let format = descriptor.format;
-        unsafe {
-            wgpu_native::device::wgpuQueueWriteTexture(
-                system.queue.0,
-                Some(&native::WGPUImageCopyTexture {
-                    nextInChain: null(),
-                    texture: device_texture,
-                    mipLevel: 0,
-                    origin: native::WGPUOrigin3D { x: 0, y: 0, z: 0 },
-                    aspect: 0,
-                }),
-                texture.data.as_ptr(),
-                texture.data.len(),
-                Some(&native::WGPUTextureDataLayout {
-                    nextInChain: null(),
-                    offset: 0,
-                    bytesPerRow: bytes_per_pixel(format) * size.width,
-                    rowsPerImage: size.height,
-                }),
-                Some(&size),
-            );
+        for (level, data) in texture.levels.iter().enumerate() {
+            write_texture_level(system.queue.0, texture, level as u32, data);
         }
         let texture_view = unsafe {
             wgpu_native::device::wgpuTextureCreateView(
-                device_texture,
+                texture.texture.0,
                 Some(&native::WGPUTextureViewDescriptor {
                     nextInChain: null(),
                     label: null(),
-                    format,
+                    format: descriptor.format,
                     dimension: texture_to_view_dimension(descriptor.dimension),
                     baseMipLevel: 0,
-                    mipLevelCount: 1,
+                    mipLevelCount: descriptor.mipLevelCount,
                     baseArrayLayer: 0,
                     arrayLayerCount: 1,
                     aspect: native::WGPUTextureAspect_All,
@@ -348,113 +844,236 @@ fn update_textures(system: &mut System, need_all: bool) {
     }
 }
 
-fn ensure_swap_chain(system: &mut System) -> bool {
-    if !system.swap_chain.0.is_null() {
-        return false;
-    }
-    if system.gpu.depth_texture.0.is_null() {
-        reset_depth_texture(system);
-    }
-    let format = wgpu_surface_get_preferred_format_simple(system);
-    let size = system.window.as_ref().unwrap().inner_size();
-    system.swap_chain.0 = unsafe {
-        wgpu_native::device::wgpuDeviceCreateSwapChain(
-            system.device.0,
-            system.surface.0,
-            Some(&native::WGPUSwapChainDescriptor {
+fn write_texture_level(queue: native::WGPUQueue, texture: &GpuTexture, level: u32, data: &[u8]) {
+    let descriptor = &texture.descriptor.0;
+    let size = mip_size(descriptor.size, level);
+    // Texture create already checked the format.
+    let (bytes_per_row, rows) = texture_pitch(descriptor.format, size.width, size.height).unwrap();
+    // Compressed copies cover whole blocks, even past the edge of small levels.
+    let (block_width, block_height, _) = texture_block(descriptor.format).unwrap();
+    let extent = native::WGPUExtent3D {
+        width: size.width.next_multiple_of(block_width),
+        height: size.height.next_multiple_of(block_height),
+        ..size
+    };
+    unsafe {
+        wgpu_native::device::wgpuQueueWriteTexture(
+            queue,
+            Some(&native::WGPUImageCopyTexture {
                 nextInChain: null(),
-                label: null(),
-                usage: native::WGPUTextureUsage_RenderAttachment,
-                format,
-                width: size.width,
-                height: size.height,
-                presentMode: native::WGPUPresentMode_Fifo,
+                texture: texture.texture.0,
+                mipLevel: level,
+                origin: native::WGPUOrigin3D { x: 0, y: 0, z: 0 },
+                aspect: 0,
             }),
-        )
+            data.as_ptr(),
+            data.len(),
+            Some(&native::WGPUTextureDataLayout {
+                nextInChain: null(),
+                offset: 0,
+                bytesPerRow: bytes_per_row,
+                rowsPerImage: rows,
+            }),
+            Some(&extent),
+        );
+    }
+}
+
+fn mip_size(size: native::WGPUExtent3D, level: u32) -> native::WGPUExtent3D {
+    native::WGPUExtent3D {
+        width: (size.width >> level).max(1),
+        height: (size.height >> level).max(1),
+        depthOrArrayLayers: size.depthOrArrayLayers,
+    }
+}
+
+/// Box filters each level after the first from the one before.
+fn generate_mips(texture: &mut GpuTexture) {
+    let descriptor = &texture.descriptor.0;
+    for level in 1..texture.levels.len() {
+        let size = mip_size(descriptor.size, level as u32 - 1);
+        texture.levels[level] = downsample(
+            descriptor.format,
+            size.width,
+            size.height,
+            &texture.levels[level - 1],
+        );
+    }
+}
+
+/// Channel count, bytes per channel, and whether sRGB for formats that
+/// downsample supports, where 2 and 4 bytes are floats.
+fn mip_channels(format: native::WGPUTextureFormat) -> Option<(usize, usize, bool)> {
+    let channels = match format {
+        native::WGPUTextureFormat_R8Unorm => (1, 1, false),
+        native::WGPUTextureFormat_RG8Unorm => (2, 1, false),
+        native::WGPUTextureFormat_RGBA8Unorm | native::WGPUTextureFormat_BGRA8Unorm => {
+            (4, 1, false)
+        }
+        native::WGPUTextureFormat_RGBA8UnormSrgb | native::WGPUTextureFormat_BGRA8UnormSrgb => {
+            (4, 1, true)
+        }
+        native::WGPUTextureFormat_R16Float => (1, 2, false),
+        native::WGPUTextureFormat_RG16Float => (2, 2, false),
+        native::WGPUTextureFormat_RGBA16Float => (4, 2, false),
+        native::WGPUTextureFormat_R32Float => (1, 4, false),
+        native::WGPUTextureFormat_RG32Float => (2, 4, false),
+        native::WGPUTextureFormat_RGBA32Float => (4, 4, false),
+        _ => return None,
     };
-    true
+    Some(channels)
 }
 
-fn taca_gpu_ensure_device(system: &mut System) -> bool {
-    // TODO Some clean flag to skip all these checks?
-    // Instance, surface, & adapter.
-    wgpu_ensure_instance_simple(system);
-    wgpu_instance_ensure_surface_simple(system);
-    wgpu_instance_ensure_adapter_simple(system);
-    // Device & queue.
-    let had_device = !system.device.0.is_null();
-    let mut any_change = check_limits(system) || !had_device;
-    if any_change || !had_device {
-        if had_device {
-            unsafe {
-                // TODO Drop written buffers!
-                // TODO Drop any other dependencies!
-                wgpuDeviceDrop(system.device.0);
+/// Halves each dimension, averaging sRGB color in linear space.
+fn downsample(format: native::WGPUTextureFormat, width: u32, height: u32, data: &[u8]) -> Vec<u8> {
+    // Texture create only allows generated mips for supported formats.
+    let (channels, bytes, srgb) = mip_channels(format).unwrap();
+    let read = |x: u32, y: u32, channel: usize| -> f32 {
+        let index = ((y * width + x) as usize * channels + channel) * bytes;
+        let data = &data[index..index + bytes];
+        match bytes {
+            2 => f16_to_f32(u16::from_le_bytes(data.try_into().unwrap())),
+            4 => f32::from_le_bytes(data.try_into().unwrap()),
+            _ => {
+                let value = data[0] as f32 / 255.0;
+                match srgb && channel < 3 {
+                    true => srgb_to_linear(value),
+                    false => value,
+                }
             }
-            system.device.0 = null_mut();
         }
-        if wgpu_adapter_ensure_device_simple(system) {
-            // Always get error messages.
-            wgpu_device_ensure_uncaptured_error_callback_simple(system);
+    };
+    let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+    let mut next = Vec::with_capacity(data.len() / 4);
+    for y in 0..next_height {
+        // Odd sizes repeat the edge.
+        let (y0, y1) = ((y * 2).min(height - 1), (y * 2 + 1).min(height - 1));
+        for x in 0..next_width {
+            let (x0, x1) = ((x * 2).min(width - 1), (x * 2 + 1).min(width - 1));
+            for channel in 0..channels {
+                let value = (read(x0, y0, channel)
+                    + read(x1, y0, channel)
+                    + read(x0, y1, channel)
+                    + read(x1, y1, channel))
+                    / 4.0;
+                match bytes {
+                    2 => next.extend_from_slice(&f32_to_f16(value).to_le_bytes()),
+                    4 => next.extend_from_slice(&value.to_le_bytes()),
+                    _ => {
+                        let value = match srgb && channel < 3 {
+                            true => linear_to_srgb(value),
+                            false => value,
+                        };
+                        next.push((value * 255.0).round().clamp(0.0, 255.0) as u8);
+                    }
+                }
+            }
         }
-        wgpu_device_ensure_queue_simple(system);
     }
-    // Buffers.
-    update_buffers(system, any_change);
-    update_textures(system, any_change);
-    any_change |= ensure_swap_chain(system);
-    any_change
+    next
 }
 
-fn taca_gpu_ensure_pipeline(system: &mut System) {
-    let had_pipeline = !system.gpu.pipeline.0.is_null();
-    let any_change = taca_gpu_ensure_device(system) || !had_pipeline;
-    if !any_change {
-        return;
+fn f16_to_f32(half: u16) -> f32 {
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+    let magnitude = match exponent {
+        // Subnormal.
+        0 => mantissa as f32 * (-24f32).exp2(),
+        0x1f => match mantissa {
+            0 => f32::INFINITY,
+            _ => f32::NAN,
+        },
+        _ => f32::from_bits(((exponent + 112) << 23) | (mantissa << 13)),
+    };
+    match half & 0x8000 != 0 {
+        true => -magnitude,
+        false => magnitude,
     }
-    if had_pipeline {
-        // TODO Should this have been dropped already if we redid the device?
-        unsafe {
-            wgpuRenderPipelineDrop(system.gpu.pipeline.0);
+}
+
+/// Rounds to nearest even, like gpus do.
+fn f32_to_f16(value: f32) -> u16 {
+    let sign = ((value.to_bits() >> 16) & 0x8000) as u16;
+    let magnitude = value.abs();
+    let half = if magnitude.is_nan() {
+        0x7e00
+    } else if magnitude >= 65520.0 {
+        // Past the largest finite half after rounding.
+        0x7c00
+    } else if magnitude < (-14f32).exp2() {
+        // Subnormal, where rounding up to 0x400 gives the smallest normal.
+        (magnitude * 24f32.exp2()).round_ties_even() as u16
+    } else {
+        let bits = magnitude.to_bits();
+        let mut half = (((bits >> 23) - 112) << 10) | ((bits & 0x7fffff) >> 13);
+        let rest = bits & 0x1fff;
+        if rest > 0x1000 || (rest == 0x1000 && half & 1 == 1) {
+            // Carries into the exponent as needed.
+            half += 1;
         }
-        system.gpu.pipeline.0 = null_mut();
+        half as u16
+    };
+    sign | half
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    match value <= 0.04045 {
+        true => value / 12.92,
+        false => ((value + 0.055) / 1.055).powf(2.4),
     }
-    let mut bind_group_layout_entries = Vec::<native::WGPUBindGroupLayoutEntry>::new();
-    let mut bind_group_entries = Vec::<native::WGPUBindGroupEntry>::new();
-    for buffer in &system.gpu.buffers {
-        let buffer = buffer.lock().unwrap();
-        let bind_group_layout_entry = match buffer.detail {
-            GpuBufferDetail::Uniform { binding } => native::WGPUBindGroupLayoutEntry {
-                nextInChain: null(),
-                binding,
-                visibility: native::WGPUShaderStage_Vertex | native::WGPUShaderStage_Fragment,
-                buffer: native::WGPUBufferBindingLayout {
-                    nextInChain: null(),
-                    type_: native::WGPUBufferBindingType_Uniform,
-                    hasDynamicOffset: false,
-                    minBindingSize: 0,
-                },
-                sampler: native::WGPUSamplerBindingLayout {
-                    nextInChain: null(),
-                    type_: native::WGPUSamplerBindingType_Undefined,
-                },
-                texture: native::WGPUTextureBindingLayout {
-                    nextInChain: null(),
-                    sampleType: native::WGPUTextureSampleType_Undefined,
-                    viewDimension: native::WGPUTextureViewDimension_Undefined,
-                    multisampled: false,
-                },
-                storageTexture: native::WGPUStorageTextureBindingLayout {
-                    nextInChain: null(),
-                    access: native::WGPUStorageTextureAccess_Undefined,
-                    format: native::WGPUTextureFormat_Undefined,
-                    viewDimension: native::WGPUTextureViewDimension_Undefined,
-                },
-            },
-            _ => continue,
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    match value <= 0.0031308 {
+        true => value * 12.92,
+        false => 1.055 * value.powf(1.0 / 2.4) - 0.055,
+    }
+}
+
+fn update_samplers(system: &mut System, need_all: bool) {
+    for sampler in &mut system.gpu.samplers {
+        if !(need_all || sampler.sampler.0.is_null()) {
+            continue;
+        }
+        sampler.sampler.0 = unsafe {
+            wgpu_native::device::wgpuDeviceCreateSampler(
+                system.device.0,
+                Some(&sampler.descriptor.to_native()),
+            )
         };
-        let bind_group_entry = match buffer.detail {
-            GpuBufferDetail::Uniform { binding } => native::WGPUBindGroupEntry {
+    }
+}
+
+/// Binds current gpu buffers and textures, since resized buffers are new.
+fn reset_bind_group(system: &mut System) {
+    for bind_group in [system.gpu.bind_group.0, system.gpu.compute_bind_group.0] {
+        if !bind_group.is_null() {
+            unsafe { wgpu_native::device::wgpuBindGroupDrop(bind_group) };
+        }
+    }
+    for (_, bind_group) in system.gpu.target_bind_groups.drain() {
+        unsafe { wgpu_native::device::wgpuBindGroupDrop(bind_group.0) };
+    }
+    let bind_group_entries = bind_group_entries(system, GpuTarget::default());
+    let bind_group = create_bind_group(system, system.gpu.bind_group_layout.0, &bind_group_entries);
+    let compute_bind_group = create_bind_group(
+        system,
+        system.gpu.compute_bind_group_layout.0,
+        &bind_group_entries,
+    );
+    system.gpu.bind_group.0 = bind_group;
+    system.gpu.compute_bind_group.0 = compute_bind_group;
+}
+
+/// Binds everything, except with dummies for textures attached to the target.
+fn bind_group_entries(system: &System, target: GpuTarget) -> Vec<native::WGPUBindGroupEntry> {
+    let mut bind_group_entries = Vec::<native::WGPUBindGroupEntry>::new();
+    for buffer in &system.gpu.buffers {
+        let buffer = buffer.lock().unwrap();
+        if let GpuBufferDetail::Storage { binding, .. } | GpuBufferDetail::Uniform { binding } =
+            buffer.detail
+        {
+            bind_group_entries.push(native::WGPUBindGroupEntry {
                 nextInChain: null(),
                 binding,
                 buffer: buffer.buffer.0,
@@ -462,78 +1081,296 @@ fn taca_gpu_ensure_pipeline(system: &mut System) {
                 size: buffer.size as u64,
                 sampler: null_mut(),
                 textureView: null_mut(),
-            },
-            _ => panic!(),
-        };
-        bind_group_layout_entries.push(bind_group_layout_entry);
-        bind_group_entries.push(bind_group_entry);
+            });
+        }
     }
-    for texture in &system.gpu.textures {
-        let bind_group_layout_entry = native::WGPUBindGroupLayoutEntry {
-            nextInChain: null(),
-            binding: texture.binding,
-            visibility: native::WGPUShaderStage_Fragment,
-            buffer: native::WGPUBufferBindingLayout {
-                nextInChain: null(),
-                type_: native::WGPUBufferBindingType_Undefined,
-                hasDynamicOffset: false,
-                minBindingSize: 0,
-            },
-            sampler: native::WGPUSamplerBindingLayout {
-                nextInChain: null(),
-                type_: native::WGPUSamplerBindingType_NonFiltering,
-            },
-            texture: native::WGPUTextureBindingLayout {
-                nextInChain: null(),
-                sampleType: native::WGPUTextureSampleType_Float,
-                viewDimension: texture_to_view_dimension(texture.descriptor.0.dimension),
-                multisampled: false,
-            },
-            storageTexture: native::WGPUStorageTextureBindingLayout {
-                nextInChain: null(),
-                access: native::WGPUStorageTextureAccess_Undefined,
-                format: native::WGPUTextureFormat_Undefined,
-                viewDimension: native::WGPUTextureViewDimension_Undefined,
-            },
+    for (index, texture) in system.gpu.textures.iter().enumerate() {
+        let handle = index as u32 + 1;
+        let texture_view = if handle != target.color && handle != target.depth {
+            texture.texture_view.as_ref().unwrap().0
+        } else if texture_sample_type(texture.descriptor.0.format)
+            == native::WGPUTextureSampleType_Depth
+        {
+            system.gpu.dummy_textures.depth_view.0
+        } else {
+            system.gpu.dummy_textures.color_view.0
         };
-        let bind_group_entry = native::WGPUBindGroupEntry {
+        bind_group_entries.push(native::WGPUBindGroupEntry {
             nextInChain: null(),
             binding: texture.binding,
             buffer: null_mut(),
             offset: 0,
             size: 0,
             sampler: null_mut(),
-            textureView: texture.texture_view.as_ref().unwrap().0,
-        };
-        bind_group_layout_entries.push(bind_group_layout_entry);
-        bind_group_entries.push(bind_group_entry);
+            textureView: texture_view,
+        });
     }
-    // TODO Store this for later.
-    let layout = unsafe {
-        wgpu_native::device::wgpuDeviceCreateBindGroupLayout(
+    for sampler in &system.gpu.samplers {
+        bind_group_entries.push(native::WGPUBindGroupEntry {
+            nextInChain: null(),
+            binding: sampler.binding,
+            buffer: null_mut(),
+            offset: 0,
+            size: 0,
+            sampler: sampler.sampler.0,
+            textureView: null_mut(),
+        });
+    }
+    bind_group_entries
+}
+
+fn create_bind_group(
+    system: &System,
+    layout: native::WGPUBindGroupLayout,
+    entries: &[native::WGPUBindGroupEntry],
+) -> native::WGPUBindGroup {
+    unsafe {
+        wgpu_native::device::wgpuDeviceCreateBindGroup(
             system.device.0,
-            Some(&native::WGPUBindGroupLayoutDescriptor {
+            Some(&native::WGPUBindGroupDescriptor {
                 nextInChain: null(),
                 label: null(),
-                entryCount: bind_group_layout_entries.len() as u32,
-                entries: bind_group_layout_entries.as_ptr(),
+                layout,
+                entryCount: entries.len() as u32,
+                entries: entries.as_ptr(),
             }),
         )
+    }
+}
+
+/// The bind group for draws in the current pass.
+fn ensure_target_bind_group(system: &mut System) -> native::WGPUBindGroup {
+    let target = system.gpu.target;
+    if target == GpuTarget::default() {
+        return system.gpu.bind_group.0;
+    }
+    if let Some(bind_group) = system.gpu.target_bind_groups.get(&target) {
+        return bind_group.0;
+    }
+    ensure_dummy_textures(system);
+    let entries = bind_group_entries(system, target);
+    let bind_group = create_bind_group(system, system.gpu.bind_group_layout.0, &entries);
+    system
+        .gpu
+        .target_bind_groups
+        .insert(target, WGPUBindGroup(bind_group));
+    bind_group
+}
+
+fn ensure_dummy_textures(system: &mut System) {
+    if !system.gpu.dummy_textures.color.0.is_null() {
+        return;
+    }
+    let create = |format| unsafe {
+        let texture = wgpu_native::device::wgpuDeviceCreateTexture(
+            system.device.0,
+            Some(&native::WGPUTextureDescriptor {
+                nextInChain: null(),
+                label: null(),
+                usage: native::WGPUTextureUsage_TextureBinding,
+                dimension: native::WGPUTextureDimension_2D,
+                size: native::WGPUExtent3D {
+                    width: 1,
+                    height: 1,
+                    depthOrArrayLayers: 1,
+                },
+                format,
+                mipLevelCount: 1,
+                sampleCount: 1,
+                viewFormatCount: 0,
+                viewFormats: null(),
+            }),
+        );
+        let view = wgpu_native::device::wgpuTextureCreateView(texture, None);
+        (WGPUTexture(texture), WGPUTextureView(view))
     };
-    system.gpu.bind_group.0 = unsafe {
-        wgpu_native::device::wgpuDeviceCreateBindGroup(
+    let (color, color_view) = create(native::WGPUTextureFormat_RGBA8Unorm);
+    let (depth, depth_view) = create(native::WGPUTextureFormat_Depth32Float);
+    system.gpu.dummy_textures = GpuDummyTextures {
+        color,
+        color_view,
+        depth,
+        depth_view,
+    };
+}
+
+fn ensure_swap_chain(system: &mut System) -> bool {
+    if system.has_swap_chain() {
+        return false;
+    }
+    if system.gpu.depth_texture.0.is_null() {
+        reset_depth_texture(system);
+    }
+    let format = wgpu_surface_get_preferred_format_simple(system);
+    let size = system.inner_size();
+    wgpu_device_create_swap_chain_simple(
+        system,
+        &native::WGPUSwapChainDescriptor {
+            nextInChain: null(),
+            label: null(),
+            usage: native::WGPUTextureUsage_RenderAttachment,
+            format,
+            width: size.width,
+            height: size.height,
+            presentMode: native::WGPUPresentMode_Fifo,
+        },
+    );
+    true
+}
+
+fn taca_gpu_ensure_device(system: &mut System) -> bool {
+    // TODO Some clean flag to skip all these checks?
+    // Instance, surface, & adapter.
+    wgpu_ensure_instance_simple(system);
+    wgpu_instance_ensure_surface_simple(system);
+    wgpu_instance_ensure_adapter_simple(system);
+    // Device & queue.
+    let had_device = !system.device.0.is_null();
+    let mut any_change = check_limits(system) || !had_device;
+    if any_change || !had_device {
+        if had_device {
+            unsafe {
+                // TODO Drop written buffers!
+                // TODO Drop any other dependencies!
+                wgpuDeviceDrop(system.device.0);
+            }
+            system.device.0 = null_mut();
+        }
+        if wgpu_adapter_ensure_device_simple(system) {
+            // Always get error messages.
+            wgpu_device_ensure_uncaptured_error_callback_simple(system);
+        }
+        wgpu_device_ensure_queue_simple(system);
+    }
+    // Buffers.
+    update_buffers(system, any_change);
+    update_textures(system, any_change);
+    update_samplers(system, any_change);
+    any_change |= ensure_swap_chain(system);
+    any_change
+}
+
+fn taca_gpu_ensure_pipeline_layout(system: &mut System) {
+    let had_layout = !system.gpu.pipeline_layout.0.is_null();
+    let any_change = taca_gpu_ensure_device(system) || !had_layout;
+    if !any_change {
+        return;
+    }
+    if had_layout {
+        // TODO Should these have been dropped already if we redid the device?
+        for (_, pipeline) in system.gpu.render_pipelines.drain() {
+            unsafe {
+                wgpuRenderPipelineDrop(pipeline.0);
+            }
+        }
+        for compute in &mut system.gpu.computes {
+            if !compute.pipeline.0.is_null() {
+                unsafe { wgpu_native::device::wgpuComputePipelineDrop(compute.pipeline.0) };
+                compute.pipeline.0 = null_mut();
+            }
+        }
+        for shader in &mut system.gpu.shaders {
+            shader.module = 0;
+        }
+        drop_blit(system);
+        system.gpu.blit.shader = 0;
+        // Belonged to the old device.
+        system.gpu.dummy_textures = Default::default();
+    }
+    let layout = create_bind_group_layout(system, false);
+    let compute_layout = create_bind_group_layout(system, true);
+    system.gpu.bind_group.0 = null_mut();
+    system.gpu.bind_group_layout.0 = layout;
+    system.gpu.compute_bind_group.0 = null_mut();
+    system.gpu.compute_bind_group_layout.0 = compute_layout;
+    reset_bind_group(system);
+    system.gpu.pipeline_layout.0 = create_pipeline_layout(system, layout);
+    system.gpu.compute_pipeline_layout.0 = create_pipeline_layout(system, compute_layout);
+}
+
+/// Binds all buffers and textures, where only compute writes storage textures.
+fn create_bind_group_layout(system: &System, compute: bool) -> native::WGPUBindGroupLayout {
+    let mut bind_group_layout_entries = Vec::<native::WGPUBindGroupLayoutEntry>::new();
+    let (buffer_stages, texture_stages) = match compute {
+        true => (
+            native::WGPUShaderStage_Compute,
+            native::WGPUShaderStage_Compute,
+        ),
+        false => (
+            native::WGPUShaderStage_Vertex | native::WGPUShaderStage_Fragment,
+            native::WGPUShaderStage_Fragment,
+        ),
+    };
+    for buffer in &system.gpu.buffers {
+        let buffer = buffer.lock().unwrap();
+        let (binding, visibility, type_) = match buffer.detail {
+            GpuBufferDetail::Storage { binding, read_only } => match read_only {
+                true => (
+                    binding,
+                    buffer_stages,
+                    native::WGPUBufferBindingType_ReadOnlyStorage,
+                ),
+                // Vertex shaders can't write storage.
+                false => (
+                    binding,
+                    buffer_stages & !native::WGPUShaderStage_Vertex,
+                    native::WGPUBufferBindingType_Storage,
+                ),
+            },
+            GpuBufferDetail::Uniform { binding } => (
+                binding,
+                buffer_stages,
+                native::WGPUBufferBindingType_Uniform,
+            ),
+            _ => continue,
+        };
+        let mut entry = empty_bind_group_layout_entry(binding, visibility);
+        entry.buffer.type_ = type_;
+        bind_group_layout_entries.push(entry);
+    }
+    for texture in &system.gpu.textures {
+        let mut entry = empty_bind_group_layout_entry(texture.binding, texture_stages);
+        let view_dimension = texture_to_view_dimension(texture.descriptor.0.dimension);
+        if compute && texture.storage {
+            entry.storageTexture = native::WGPUStorageTextureBindingLayout {
+                nextInChain: null(),
+                access: native::WGPUStorageTextureAccess_WriteOnly,
+                format: texture.descriptor.0.format,
+                viewDimension: view_dimension,
+            };
+        } else {
+            entry.texture = native::WGPUTextureBindingLayout {
+                nextInChain: null(),
+                sampleType: texture_sample_type(texture.descriptor.0.format),
+                viewDimension: view_dimension,
+                multisampled: false,
+            };
+        }
+        bind_group_layout_entries.push(entry);
+    }
+    for sampler in &system.gpu.samplers {
+        let mut entry = empty_bind_group_layout_entry(sampler.binding, texture_stages);
+        entry.sampler.type_ = sampler.binding_type();
+        bind_group_layout_entries.push(entry);
+    }
+    unsafe {
+        wgpu_native::device::wgpuDeviceCreateBindGroupLayout(
             system.device.0,
-            Some(&native::WGPUBindGroupDescriptor {
+            Some(&native::WGPUBindGroupLayoutDescriptor {
                 nextInChain: null(),
                 label: null(),
-                layout,
-                entryCount: bind_group_entries.len() as u32,
-                entries: bind_group_entries.as_ptr(),
+                entryCount: bind_group_layout_entries.len() as u32,
+                entries: bind_group_layout_entries.as_ptr(),
             }),
         )
-    };
-    // TODO Store this for later.
-    let pipeline_layout = unsafe {
+    }
+}
+
+fn create_pipeline_layout(
+    system: &System,
+    layout: native::WGPUBindGroupLayout,
+) -> native::WGPUPipelineLayout {
+    unsafe {
         wgpu_native::device::wgpuDeviceCreatePipelineLayout(
             system.device.0,
             Some(&native::WGPUPipelineLayoutDescriptor {
@@ -543,36 +1380,143 @@ fn taca_gpu_ensure_pipeline(system: &mut System) {
                 bindGroupLayouts: &layout,
             }),
         )
+    }
+}
+
+fn empty_bind_group_layout_entry(
+    binding: u32,
+    visibility: native::WGPUShaderStageFlags,
+) -> native::WGPUBindGroupLayoutEntry {
+    native::WGPUBindGroupLayoutEntry {
+        nextInChain: null(),
+        binding,
+        visibility,
+        buffer: native::WGPUBufferBindingLayout {
+            nextInChain: null(),
+            type_: native::WGPUBufferBindingType_Undefined,
+            hasDynamicOffset: false,
+            minBindingSize: 0,
+        },
+        sampler: native::WGPUSamplerBindingLayout {
+            nextInChain: null(),
+            type_: native::WGPUSamplerBindingType_Undefined,
+        },
+        texture: native::WGPUTextureBindingLayout {
+            nextInChain: null(),
+            sampleType: native::WGPUTextureSampleType_Undefined,
+            viewDimension: native::WGPUTextureViewDimension_Undefined,
+            multisampled: false,
+        },
+        storageTexture: native::WGPUStorageTextureBindingLayout {
+            nextInChain: null(),
+            access: native::WGPUStorageTextureAccess_Undefined,
+            format: native::WGPUTextureFormat_Undefined,
+            viewDimension: native::WGPUTextureViewDimension_Undefined,
+        },
+    }
+}
+
+fn ensure_shader_module(system: &mut System, shader: usize) -> native::WGPUShaderModule {
+    if system.gpu.shaders[shader].module == 0 {
+        let wgsl = system.gpu.shaders[shader].wgsl.clone();
+        system.gpu.shaders[shader].module =
+            wgpu_device_create_shader_module_simple(system, wgsl.as_c_str());
+    }
+    system.shaders[system.gpu.shaders[shader].module as usize - 1].0
+}
+
+/// Gets or compiles the native pipeline for the current pipeline with the
+/// given vertex buffer.
+fn ensure_render_pipeline(
+    system: &mut System,
+    buffer: &Arc<Mutex<GpuBuffer>>,
+    instance: Option<&Arc<Mutex<GpuBuffer>>>,
+) -> native::WGPURenderPipeline {
+    let (color_format, depth_format) = target_formats(system);
+    let topology = match system.gpu.pipeline {
+        0 => TOPOLOGIES[0],
+        pipeline => system.gpu.pipelines[pipeline as usize - 1].topology,
     };
-    let shader =
-        wgpu_device_create_shader_module_simple(system, system.gpu.shaders[0].clone().as_c_str());
-    let shader = system.shaders[shader as usize - 1].0;
-    let vertex_layouts: Vec<_> = system
-        .gpu
-        .buffers
-        .clone()
+    let (vertex, index_format) = match &buffer.lock().unwrap().detail {
+        GpuBufferDetail::Index { format, vertex } => (vertex.clone(), *format),
+        GpuBufferDetail::Vertex { .. } => (buffer.clone(), native::WGPUIndexFormat_Undefined),
+        _ => panic!(),
+    };
+    let strip_index_format = match topology {
+        native::WGPUPrimitiveTopology_LineStrip | native::WGPUPrimitiveTopology_TriangleStrip => {
+            index_format
+        }
+        _ => native::WGPUIndexFormat_Undefined,
+    };
+    // Keep locked while the native layouts point into them.
+    let vertex = vertex.lock().unwrap();
+    let instance = instance.map(|instance| instance.lock().unwrap());
+    let layouts: Vec<_> = [Some(&vertex), instance.as_ref()]
+        .into_iter()
+        .flatten()
+        .map(|buffer| {
+            extract_enum_value!(&buffer.detail, GpuBufferDetail::Vertex { layout } => layout)
+        })
+        .collect();
+    let key = PipelineKey {
+        blend_mode: system.gpu.blend_mode,
+        color_format,
+        depth_format,
+        layouts: layouts.iter().map(|layout| layout.key()).collect(),
+        pipeline: system.gpu.pipeline,
+        strip_index_format,
+    };
+    if let Some(pipeline) = system.gpu.render_pipelines.get(&key) {
+        return pipeline.0;
+    }
+    let mut info = match system.gpu.pipeline {
+        0 => GpuPipeline::default(),
+        pipeline => system.gpu.pipelines[pipeline as usize - 1].clone(),
+    };
+    if key.blend_mode != GpuBlendMode::Default {
+        info.blend = key.blend_mode.blend_state();
+    }
+    let shader = ensure_shader_module(system, info.shader);
+    let vertex_layouts: Vec<_> = layouts
         .iter()
-        .filter(|it| matches!(it.lock().unwrap().detail, GpuBufferDetail::Vertex { .. }))
-        .enumerate()
-        .map(|(slot, buffer)| {
-            let buffer = &mut buffer.lock().unwrap();
-            let layout =
-                extract_enum_value!(&mut buffer.detail, GpuBufferDetail::Vertex { layout } => layout);
-            layout.slot = slot as u32;
-            native::WGPUVertexBufferLayout {
-                arrayStride: layout.array_stride,
-                stepMode: layout.step_mode,
-                attributeCount: layout.attributes.len() as u32,
-                attributes: layout.attributes.as_ptr(),
-            }
+        .map(|layout| native::WGPUVertexBufferLayout {
+            arrayStride: layout.array_stride,
+            stepMode: layout.step_mode,
+            attributeCount: layout.attributes.len() as u32,
+            attributes: layout.attributes.as_ptr(),
         })
         .collect();
-    let format = wgpu_surface_get_preferred_format_simple(system);
     let target = native::WGPUColorTargetState {
         nextInChain: null(),
-        format,
-        blend: null(),
-        writeMask: native::WGPUColorWriteMask_All,
+        format: color_format,
+        blend: match &info.blend {
+            Some(blend) => blend,
+            None => null(),
+        },
+        writeMask: info.write_mask,
+    };
+    let depth_stencil = native::WGPUDepthStencilState {
+        nextInChain: null(),
+        format: depth_format,
+        depthWriteEnabled: info.depth_write,
+        depthCompare: info.depth_compare,
+        stencilFront: native::WGPUStencilFaceState {
+            compare: native::WGPUCompareFunction_Always,
+            failOp: native::WGPUStencilOperation_Keep,
+            depthFailOp: native::WGPUStencilOperation_Keep,
+            passOp: native::WGPUStencilOperation_Keep,
+        },
+        stencilBack: native::WGPUStencilFaceState {
+            compare: native::WGPUCompareFunction_Always,
+            failOp: native::WGPUStencilOperation_Keep,
+            depthFailOp: native::WGPUStencilOperation_Keep,
+            passOp: native::WGPUStencilOperation_Keep,
+        },
+        stencilReadMask: 0,
+        stencilWriteMask: 0,
+        depthBias: 0,
+        depthBiasSlopeScale: 0.0,
+        depthBiasClamp: 0.0,
     };
     let pipeline = unsafe {
         wgpu_native::device::wgpuDeviceCreateRenderPipeline(
@@ -580,11 +1524,11 @@ fn taca_gpu_ensure_pipeline(system: &mut System) {
             Some(&native::WGPURenderPipelineDescriptor {
                 nextInChain: null(),
                 label: null(),
-                layout: pipeline_layout,
+                layout: system.gpu.pipeline_layout.0,
                 vertex: native::WGPUVertexState {
                     nextInChain: null(),
                     module: shader,
-                    entryPoint: "vs_main\0".as_bytes().as_ptr() as *const i8,
+                    entryPoint: info.vertex_entry.as_ptr(),
                     constantCount: 0,
                     constants: null(),
                     bufferCount: vertex_layouts.len() as u32,
@@ -592,33 +1536,14 @@ fn taca_gpu_ensure_pipeline(system: &mut System) {
                 },
                 primitive: native::WGPUPrimitiveState {
                     nextInChain: null(),
-                    topology: native::WGPUPrimitiveTopology_TriangleList,
-                    stripIndexFormat: native::WGPUIndexFormat_Undefined,
-                    frontFace: native::WGPUFrontFace_CCW,
-                    cullMode: native::WGPUCullMode_None,
+                    topology: info.topology,
+                    stripIndexFormat: strip_index_format,
+                    frontFace: info.front_face,
+                    cullMode: info.cull_mode,
                 },
-                depthStencil: &native::WGPUDepthStencilState {
-                    nextInChain: null(),
-                    format: native::WGPUTextureFormat_Depth24Plus,
-                    depthWriteEnabled: true,
-                    depthCompare: native::WGPUCompareFunction_Less,
-                    stencilFront: native::WGPUStencilFaceState {
-                        compare: native::WGPUCompareFunction_Always,
-                        failOp: native::WGPUStencilOperation_Keep,
-                        depthFailOp: native::WGPUStencilOperation_Keep,
-                        passOp: native::WGPUStencilOperation_Keep,
-                    },
-                    stencilBack: native::WGPUStencilFaceState {
-                        compare: native::WGPUCompareFunction_Always,
-                        failOp: native::WGPUStencilOperation_Keep,
-                        depthFailOp: native::WGPUStencilOperation_Keep,
-                        passOp: native::WGPUStencilOperation_Keep,
-                    },
-                    stencilReadMask: 0,
-                    stencilWriteMask: 0,
-                    depthBias: 0,
-                    depthBiasSlopeScale: 0.0,
-                    depthBiasClamp: 0.0,
+                depthStencil: match depth_format {
+                    native::WGPUTextureFormat_Undefined => null(),
+                    _ => &depth_stencil,
                 },
                 multisample: native::WGPUMultisampleState {
                     nextInChain: null(),
@@ -629,17 +1554,43 @@ fn taca_gpu_ensure_pipeline(system: &mut System) {
                 fragment: &native::WGPUFragmentState {
                     nextInChain: null(),
                     module: shader,
-                    entryPoint: "fs_main\0".as_bytes().as_ptr() as *const i8,
+                    entryPoint: info.fragment_entry.as_ptr(),
                     constantCount: 0,
                     constants: null(),
-                    targetCount: 1,
+                    // Depth-only passes such as for shadow maps have none.
+                    targetCount: (color_format != native::WGPUTextureFormat_Undefined) as u32,
                     targets: &target,
                 } as *const native::WGPUFragmentState,
             }),
         )
     };
     assert_ne!(null(), pipeline);
-    system.gpu.pipeline.0 = pipeline;
+    system
+        .gpu
+        .render_pipelines
+        .insert(key, WGPURenderPipeline(pipeline));
+    pipeline
+}
+
+/// Color and depth formats of the current target, or undefined for none.
+fn target_formats(system: &mut System) -> (native::WGPUTextureFormat, native::WGPUTextureFormat) {
+    let target = system.gpu.target;
+    if target == GpuTarget::default() {
+        return (
+            wgpu_surface_get_preferred_format_simple(system),
+            native::WGPUTextureFormat_Depth24Plus,
+        );
+    }
+    let format = |texture: u32| match texture {
+        0 => native::WGPUTextureFormat_Undefined,
+        _ => {
+            system.gpu.textures[texture as usize - 1]
+                .descriptor
+                .0
+                .format
+        }
+    };
+    (format(target.color), format(target.depth))
 }
 
 fn texture_to_view_dimension(dimension: u32) -> u32 {
@@ -664,8 +1615,9 @@ fn reset_depth_texture(system: &mut System) {
         }
     }
     // In with the new.
+    system.gpu.depth_texture_fresh = true;
     let depth_texture_format = native::WGPUTextureFormat_Depth24Plus;
-    let size = system.window.as_ref().unwrap().inner_size();
+    let size = system.inner_size();
     system.gpu.depth_texture.0 = unsafe {
         wgpu_native::device::wgpuDeviceCreateTexture(
             system.device.0,
@@ -706,36 +1658,52 @@ fn reset_depth_texture(system: &mut System) {
 }
 
 fn taca_gpu_ensure_render_pass(system: &mut System) {
-    taca_gpu_ensure_pipeline(system);
+    taca_gpu_ensure_pipeline_layout(system);
     if system.gpu.render_texture_view.0.is_null() {
-        system.gpu.render_texture_view.0 =
-            unsafe { wgpu_native::device::wgpuSwapChainGetCurrentTextureView(system.swap_chain.0) };
+        system.gpu.render_texture_view.0 = wgpu_swap_chain_get_current_texture_view_simple(system);
     }
     wgpu_device_ensure_command_encoder_simple(system);
+    if system.render_pass.0.is_null() && system.gpu.target != GpuTarget::default() {
+        begin_target_pass(system);
+    }
     if system.render_pass.0.is_null() {
+        let mut frame = system.gpu.frame;
+        if !system.gpu.frame_begun {
+            // Swap chain textures don't keep old frames, but offscreen does.
+            system.gpu.blit.needed = frame.load_color && system.offscreen.is_none();
+        } else {
+            frame.load_color = true;
+            frame.load_depth = true;
+        }
+        system.gpu.frame_begun = true;
+        let (view, fresh) = match system.gpu.blit.needed {
+            true => (ensure_frame_texture(system), system.gpu.frame_texture_fresh),
+            false => (system.gpu.render_texture_view.0, false),
+        };
+        let load_op = |load: bool, fresh: bool| match load && !fresh {
+            true => native::WGPULoadOp_Load,
+            false => native::WGPULoadOp_Clear,
+        };
         let color_attachment = native::WGPURenderPassColorAttachment {
-            view: system.gpu.render_texture_view.0,
+            view,
             resolveTarget: std::ptr::null_mut(),
-            loadOp: native::WGPULoadOp_Clear,
+            loadOp: load_op(frame.load_color, fresh),
             storeOp: native::WGPUStoreOp_Store,
-            clearValue: native::WGPUColor {
-                r: 0.0,
-                g: 0.0,
-                b: 0.0,
-                a: 0.0,
-            },
+            clearValue: frame.clear_color,
         };
         let depth_stencil_attachment = native::WGPURenderPassDepthStencilAttachment {
             view: system.gpu.depth_texture_view.0,
-            depthLoadOp: native::WGPULoadOp_Clear,
+            depthLoadOp: load_op(frame.load_depth, system.gpu.depth_texture_fresh),
             depthStoreOp: native::WGPUStoreOp_Store,
-            depthClearValue: 1.0,
+            depthClearValue: frame.clear_depth,
             depthReadOnly: false,
             stencilLoadOp: native::WGPULoadOp_Clear,
             stencilStoreOp: native::WGPUStoreOp_Store,
             stencilClearValue: 0,
             stencilReadOnly: true,
         };
+        system.gpu.depth_texture_fresh = false;
+        system.gpu.frame_texture_fresh = false;
         system.render_pass.0 = unsafe {
             wgpu_native::command::wgpuCommandEncoderBeginRenderPass(
                 system.encoder.0,
@@ -752,35 +1720,350 @@ fn taca_gpu_ensure_render_pass(system: &mut System) {
             )
         };
     }
-    unsafe {
-        wgpu_native::command::wgpuRenderPassEncoderSetPipeline(
-            system.render_pass.0,
-            system.gpu.pipeline.0,
-        );
-    }
 }
 
-pub fn gpu_draw_set_buffer(system: &System, buffer: &GpuBuffer) {
-    match &buffer.detail {
-        GpuBufferDetail::Index { format, vertex } => {
-            let vertex = vertex.lock().unwrap();
-            gpu_draw_set_buffer(system, &vertex);
-            unsafe {
-                wgpu_native::command::wgpuRenderPassEncoderSetIndexBuffer(
-                    system.render_pass.0,
-                    buffer.buffer.0,
-                    *format,
-                    0,
-                    buffer.size as u64,
-                );
-            }
+/// Clears each target texture like the frame on its first pass each frame.
+fn begin_target_pass(system: &mut System) {
+    let target = system.gpu.target;
+    let frame = system.gpu.frame;
+    let mut load_op =
+        |texture: u32, load: bool| match load || !system.gpu.targets_begun.insert(texture) {
+            true => native::WGPULoadOp_Load,
+            false => native::WGPULoadOp_Clear,
+        };
+    let color_load_op = load_op(target.color, frame.load_color);
+    let depth_load_op = load_op(target.depth, frame.load_depth);
+    let view = |texture: u32| match texture {
+        0 => null_mut(),
+        _ => {
+            let texture = &system.gpu.textures[texture as usize - 1];
+            texture.texture_view.as_ref().unwrap().0
         }
-        GpuBufferDetail::Vertex { layout } => unsafe {
-            wgpu_native::command::wgpuRenderPassEncoderSetVertexBuffer(
-                system.render_pass.0,
-                layout.slot,
-                buffer.buffer.0,
-                0,
+    };
+    let color_attachment = native::WGPURenderPassColorAttachment {
+        view: view(target.color),
+        resolveTarget: null_mut(),
+        loadOp: color_load_op,
+        storeOp: native::WGPUStoreOp_Store,
+        clearValue: frame.clear_color,
+    };
+    let depth_stencil_attachment = native::WGPURenderPassDepthStencilAttachment {
+        view: view(target.depth),
+        depthLoadOp: depth_load_op,
+        depthStoreOp: native::WGPUStoreOp_Store,
+        depthClearValue: frame.clear_depth,
+        depthReadOnly: false,
+        stencilLoadOp: native::WGPULoadOp_Clear,
+        stencilStoreOp: native::WGPUStoreOp_Store,
+        stencilClearValue: 0,
+        stencilReadOnly: true,
+    };
+    system.render_pass.0 = unsafe {
+        wgpu_native::command::wgpuCommandEncoderBeginRenderPass(
+            system.encoder.0,
+            Some(&native::WGPURenderPassDescriptor {
+                nextInChain: null(),
+                label: null(),
+                colorAttachmentCount: (target.color != 0) as u32,
+                colorAttachments: &color_attachment,
+                depthStencilAttachment: match target.depth {
+                    0 => null(),
+                    _ => &depth_stencil_attachment,
+                },
+                occlusionQuerySet: null_mut(),
+                timestampWriteCount: 0,
+                timestampWrites: null(),
+            }),
+        )
+    };
+}
+
+fn end_render_pass(system: &mut System) {
+    if !system.render_pass.0.is_null() {
+        unsafe {
+            wgpu_native::command::wgpuRenderPassEncoderEnd(system.render_pass.0);
+        }
+        system.render_pass.0 = null_mut();
+    }
+}
+
+fn ensure_frame_texture(system: &mut System) -> native::WGPUTextureView {
+    if !system.gpu.frame_texture.0.is_null() {
+        return system.gpu.frame_texture_view.0;
+    }
+    let format = wgpu_surface_get_preferred_format_simple(system);
+    let size = system.inner_size();
+    system.gpu.frame_texture.0 = unsafe {
+        wgpu_native::device::wgpuDeviceCreateTexture(
+            system.device.0,
+            Some(&native::WGPUTextureDescriptor {
+                nextInChain: null(),
+                label: null(),
+                usage: native::WGPUTextureUsage_RenderAttachment
+                    | native::WGPUTextureUsage_TextureBinding,
+                dimension: native::WGPUTextureDimension_2D,
+                size: native::WGPUExtent3D {
+                    width: size.width,
+                    height: size.height,
+                    depthOrArrayLayers: 1,
+                },
+                format,
+                mipLevelCount: 1,
+                sampleCount: 1,
+                viewFormatCount: 0,
+                viewFormats: null(),
+            }),
+        )
+    };
+    system.gpu.frame_texture_view.0 = unsafe {
+        wgpu_native::device::wgpuTextureCreateView(
+            system.gpu.frame_texture.0,
+            Some(&native::WGPUTextureViewDescriptor {
+                nextInChain: null(),
+                label: null(),
+                format,
+                dimension: native::WGPUTextureViewDimension_2D,
+                baseMipLevel: 0,
+                mipLevelCount: 1,
+                baseArrayLayer: 0,
+                arrayLayerCount: 1,
+                aspect: native::WGPUTextureAspect_All,
+            }),
+        )
+    };
+    system.gpu.frame_texture_fresh = true;
+    system.gpu.frame_texture_view.0
+}
+
+fn drop_frame_texture(system: &mut System) {
+    if system.gpu.frame_texture.0.is_null() {
+        return;
+    }
+    unsafe {
+        wgpu_native::device::wgpuTextureViewDrop(system.gpu.frame_texture_view.0);
+        wgpu_native::device::wgpuTextureDrop(system.gpu.frame_texture.0);
+    }
+    system.gpu.frame_texture_view.0 = null_mut();
+    system.gpu.frame_texture.0 = null_mut();
+    // The bind group points at the old view.
+    drop_blit(system);
+}
+
+fn drop_blit(system: &mut System) {
+    let blit = &mut system.gpu.blit;
+    unsafe {
+        if !blit.bind_group.0.is_null() {
+            wgpu_native::device::wgpuBindGroupDrop(blit.bind_group.0);
+        }
+        if !blit.bind_group_layout.0.is_null() {
+            wgpu_native::device::wgpuBindGroupLayoutDrop(blit.bind_group_layout.0);
+        }
+        if !blit.pipeline.0.is_null() {
+            wgpuRenderPipelineDrop(blit.pipeline.0);
+        }
+    }
+    let (needed, shader) = (blit.needed, blit.shader);
+    system.gpu.blit = GpuBlit {
+        needed,
+        shader,
+        ..Default::default()
+    };
+}
+
+fn ensure_blit(system: &mut System) {
+    if !system.gpu.blit.pipeline.0.is_null() {
+        return;
+    }
+    let bind_group_layout_entry = native::WGPUBindGroupLayoutEntry {
+        nextInChain: null(),
+        binding: 0,
+        visibility: native::WGPUShaderStage_Fragment,
+        buffer: native::WGPUBufferBindingLayout {
+            nextInChain: null(),
+            type_: native::WGPUBufferBindingType_Undefined,
+            hasDynamicOffset: false,
+            minBindingSize: 0,
+        },
+        sampler: native::WGPUSamplerBindingLayout {
+            nextInChain: null(),
+            type_: native::WGPUSamplerBindingType_Undefined,
+        },
+        texture: native::WGPUTextureBindingLayout {
+            nextInChain: null(),
+            sampleType: native::WGPUTextureSampleType_UnfilterableFloat,
+            viewDimension: native::WGPUTextureViewDimension_2D,
+            multisampled: false,
+        },
+        storageTexture: native::WGPUStorageTextureBindingLayout {
+            nextInChain: null(),
+            access: native::WGPUStorageTextureAccess_Undefined,
+            format: native::WGPUTextureFormat_Undefined,
+            viewDimension: native::WGPUTextureViewDimension_Undefined,
+        },
+    };
+    let layout = unsafe {
+        wgpu_native::device::wgpuDeviceCreateBindGroupLayout(
+            system.device.0,
+            Some(&native::WGPUBindGroupLayoutDescriptor {
+                nextInChain: null(),
+                label: null(),
+                entryCount: 1,
+                entries: &bind_group_layout_entry,
+            }),
+        )
+    };
+    system.gpu.blit.bind_group_layout.0 = layout;
+    system.gpu.blit.bind_group.0 = unsafe {
+        wgpu_native::device::wgpuDeviceCreateBindGroup(
+            system.device.0,
+            Some(&native::WGPUBindGroupDescriptor {
+                nextInChain: null(),
+                label: null(),
+                layout,
+                entryCount: 1,
+                entries: &native::WGPUBindGroupEntry {
+                    nextInChain: null(),
+                    binding: 0,
+                    buffer: null_mut(),
+                    offset: 0,
+                    size: 0,
+                    sampler: null_mut(),
+                    textureView: system.gpu.frame_texture_view.0,
+                },
+            }),
+        )
+    };
+    let pipeline_layout = unsafe {
+        wgpu_native::device::wgpuDeviceCreatePipelineLayout(
+            system.device.0,
+            Some(&native::WGPUPipelineLayoutDescriptor {
+                nextInChain: null(),
+                label: null(),
+                bindGroupLayoutCount: 1,
+                bindGroupLayouts: &layout,
+            }),
+        )
+    };
+    if system.gpu.blit.shader == 0 {
+        let wgsl = CStr::from_bytes_with_nul(BLIT_WGSL.as_bytes()).unwrap();
+        system.gpu.blit.shader = wgpu_device_create_shader_module_simple(system, wgsl);
+    }
+    let shader = system.shaders[system.gpu.blit.shader as usize - 1].0;
+    let target = native::WGPUColorTargetState {
+        nextInChain: null(),
+        format: wgpu_surface_get_preferred_format_simple(system),
+        blend: null(),
+        writeMask: native::WGPUColorWriteMask_All,
+    };
+    system.gpu.blit.pipeline.0 = unsafe {
+        wgpu_native::device::wgpuDeviceCreateRenderPipeline(
+            system.device.0,
+            Some(&native::WGPURenderPipelineDescriptor {
+                nextInChain: null(),
+                label: null(),
+                layout: pipeline_layout,
+                vertex: native::WGPUVertexState {
+                    nextInChain: null(),
+                    module: shader,
+                    entryPoint: "vs_main\0".as_bytes().as_ptr() as *const i8,
+                    constantCount: 0,
+                    constants: null(),
+                    bufferCount: 0,
+                    buffers: null(),
+                },
+                primitive: native::WGPUPrimitiveState {
+                    nextInChain: null(),
+                    topology: native::WGPUPrimitiveTopology_TriangleList,
+                    stripIndexFormat: native::WGPUIndexFormat_Undefined,
+                    frontFace: native::WGPUFrontFace_CCW,
+                    cullMode: native::WGPUCullMode_None,
+                },
+                depthStencil: null(),
+                multisample: native::WGPUMultisampleState {
+                    nextInChain: null(),
+                    count: 1,
+                    mask: 0xFFFFFFFF,
+                    alphaToCoverageEnabled: false,
+                },
+                fragment: &native::WGPUFragmentState {
+                    nextInChain: null(),
+                    module: shader,
+                    entryPoint: "fs_main\0".as_bytes().as_ptr() as *const i8,
+                    constantCount: 0,
+                    constants: null(),
+                    targetCount: 1,
+                    targets: &target,
+                } as *const native::WGPUFragmentState,
+            }),
+        )
+    };
+    // The pipeline keeps what it needs of the layout.
+    unsafe { wgpu_native::device::wgpuPipelineLayoutDrop(pipeline_layout) };
+}
+
+/// Copies the frame texture into the swap chain texture.
+fn blit_frame_texture(system: &mut System) {
+    ensure_blit(system);
+    let color_attachment = native::WGPURenderPassColorAttachment {
+        view: system.gpu.render_texture_view.0,
+        resolveTarget: null_mut(),
+        loadOp: native::WGPULoadOp_Clear,
+        storeOp: native::WGPUStoreOp_Store,
+        clearValue: native::WGPUColor {
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
+            a: 0.0,
+        },
+    };
+    unsafe {
+        let pass = wgpu_native::command::wgpuCommandEncoderBeginRenderPass(
+            system.encoder.0,
+            Some(&native::WGPURenderPassDescriptor {
+                nextInChain: null(),
+                label: null(),
+                colorAttachmentCount: 1,
+                colorAttachments: &color_attachment,
+                depthStencilAttachment: null(),
+                occlusionQuerySet: null_mut(),
+                timestampWriteCount: 0,
+                timestampWrites: null(),
+            }),
+        );
+        wgpu_native::command::wgpuRenderPassEncoderSetPipeline(pass, system.gpu.blit.pipeline.0);
+        wgpu_native::command::wgpuRenderPassEncoderSetBindGroup(
+            pass,
+            0,
+            system.gpu.blit.bind_group.0,
+            0,
+            null(),
+        );
+        wgpu_native::command::wgpuRenderPassEncoderDraw(pass, 3, 1, 0, 0);
+        wgpu_native::command::wgpuRenderPassEncoderEnd(pass);
+    }
+}
+
+pub fn gpu_draw_set_buffer(system: &System, buffer: &GpuBuffer) {
+    match &buffer.detail {
+        GpuBufferDetail::Index { format, vertex } => {
+            let vertex = vertex.lock().unwrap();
+            gpu_draw_set_buffer(system, &vertex);
+            unsafe {
+                wgpu_native::command::wgpuRenderPassEncoderSetIndexBuffer(
+                    system.render_pass.0,
+                    buffer.buffer.0,
+                    *format,
+                    0,
+                    buffer.size as u64,
+                );
+            }
+        }
+        GpuBufferDetail::Vertex { layout } => unsafe {
+            wgpu_native::command::wgpuRenderPassEncoderSetVertexBuffer(
+                system.render_pass.0,
+                layout.slot,
+                buffer.buffer.0,
+                0,
                 buffer.size as u64,
             );
         },
@@ -788,109 +2071,578 @@ pub fn gpu_draw_set_buffer(system: &System, buffer: &GpuBuffer) {
     }
 }
 
-pub fn taca_gpu_draw(mut env: FunctionEnvMut<System>, buffer: u32) {
+pub fn taca_gpu_draw(mut env: FunctionEnvMut<System>, buffer: u32) -> Result<(), RuntimeError> {
+    gpu_draw(env.data_mut(), buffer, 0, 1, Default::default())
+}
+
+/// taca_gpu_computeCreate
+pub fn taca_gpu_compute_create(
+    mut env: FunctionEnvMut<System>,
+    shader: u32,
+    entry: u32,
+) -> Result<u32, RuntimeError> {
+    let (system, store) = env.data_and_store_mut();
+    let view = system.memory.as_ref().unwrap().view(&store);
+    let shader = (shader as usize)
+        .checked_sub(1)
+        .filter(|&index| index < system.gpu.shaders.len())
+        .ok_or_else(|| RuntimeError::new(format!("Invalid shader: {shader}")))?;
+    let entry = match entry {
+        0 => CString::new("cs_main").unwrap(),
+        _ => read_cstring(WasmPtr::<u8>::new(entry), &view)?,
+    };
+    system.gpu.computes.push(GpuCompute {
+        entry,
+        pipeline: Default::default(),
+        shader,
+    });
+    Ok(system.gpu.computes.len() as u32)
+}
+
+/// taca_gpu_computeUse
+pub fn taca_gpu_compute_use(mut env: FunctionEnvMut<System>, compute: u32) {
+    let system = env.data_mut();
+    system.gpu.compute = compute;
+}
+
+/// taca_gpu_dispatch
+pub fn taca_gpu_dispatch(
+    mut env: FunctionEnvMut<System>,
+    x: u32,
+    y: u32,
+    z: u32,
+) -> Result<(), RuntimeError> {
     let system = env.data_mut();
+    taca_gpu_ensure_pipeline_layout(system);
+    let pipeline = ensure_compute_pipeline(system)?;
+    wgpu_device_ensure_command_encoder_simple(system);
+    // Compute can't run inside a render pass, so draws after resume it.
+    end_render_pass(system);
+    unsafe {
+        let pass = wgpu_native::command::wgpuCommandEncoderBeginComputePass(
+            system.encoder.0,
+            Some(&native::WGPUComputePassDescriptor {
+                nextInChain: null(),
+                label: null(),
+                timestampWriteCount: 0,
+                timestampWrites: null(),
+            }),
+        );
+        wgpu_native::command::wgpuComputePassEncoderSetPipeline(pass, pipeline);
+        wgpu_native::command::wgpuComputePassEncoderSetBindGroup(
+            pass,
+            0,
+            system.gpu.compute_bind_group.0,
+            0,
+            null(),
+        );
+        wgpu_native::command::wgpuComputePassEncoderDispatchWorkgroups(pass, x, y, z);
+        wgpu_native::command::wgpuComputePassEncoderEnd(pass);
+    }
+    Ok(())
+}
+
+fn ensure_compute_pipeline(
+    system: &mut System,
+) -> Result<native::WGPUComputePipeline, RuntimeError> {
+    let index = match system.gpu.compute {
+        0 => 0,
+        compute => compute as usize - 1,
+    };
+    if index >= system.gpu.computes.len() {
+        return Err(RuntimeError::new(
+            "Dispatch needs taca_gpu_computeCreate first",
+        ));
+    }
+    if system.gpu.computes[index].pipeline.0.is_null() {
+        let shader = system.gpu.computes[index].shader;
+        let shader = ensure_shader_module(system, shader);
+        let compute = &system.gpu.computes[index];
+        let pipeline = unsafe {
+            wgpu_native::device::wgpuDeviceCreateComputePipeline(
+                system.device.0,
+                Some(&native::WGPUComputePipelineDescriptor {
+                    nextInChain: null(),
+                    label: null(),
+                    layout: system.gpu.compute_pipeline_layout.0,
+                    compute: native::WGPUProgrammableStageDescriptor {
+                        nextInChain: null(),
+                        module: shader,
+                        entryPoint: compute.entry.as_ptr(),
+                        constantCount: 0,
+                        constants: null(),
+                    },
+                }),
+            )
+        };
+        if pipeline.is_null() {
+            let entry = compute.entry.to_string_lossy();
+            return Err(RuntimeError::new(format!(
+                "Can't create compute pipeline for entry: {entry}"
+            )));
+        }
+        system.gpu.computes[index].pipeline.0 = pipeline;
+    }
+    Ok(system.gpu.computes[index].pipeline.0)
+}
+
+/// taca_gpu_drawInstanced
+pub fn taca_gpu_draw_instanced(
+    mut env: FunctionEnvMut<System>,
+    buffer: u32,
+    instance_buffer: u32,
+    count: u32,
+) -> Result<(), RuntimeError> {
+    gpu_draw(
+        env.data_mut(),
+        buffer,
+        instance_buffer,
+        count,
+        Default::default(),
+    )
+}
+
+/// taca_gpu_drawRange
+pub fn taca_gpu_draw_range(
+    mut env: FunctionEnvMut<System>,
+    buffer: u32,
+    first: u32,
+    count: u32,
+    base_vertex: i32,
+) -> Result<(), RuntimeError> {
+    let range = GpuDrawRange {
+        first,
+        count,
+        base_vertex,
+    };
+    gpu_draw(env.data_mut(), buffer, 0, 1, range)
+}
+
+/// Elements of the drawn buffer, which are indices for index buffers or else
+/// vertices.
+#[derive(Clone, Copy, Default)]
+struct GpuDrawRange {
+    first: u32,
+    /// Zero means through the end of the buffer.
+    count: u32,
+    /// Added to each index, so only for index buffers.
+    base_vertex: i32,
+}
+
+impl GpuDrawRange {
+    /// The count to draw if the range fits in the total without overflow.
+    fn count_in(&self, total: u32) -> Result<u32, RuntimeError> {
+        let count = match self.count {
+            0 => total.saturating_sub(self.first),
+            _ => self.count,
+        };
+        match self.first.checked_add(count) {
+            Some(end) if end <= total => Ok(count),
+            _ => Err(RuntimeError::new(format!(
+                "Draw of {count} at {} past buffer end: {total}",
+                self.first
+            ))),
+        }
+    }
+}
+
+/// Draws with optional instance buffer, where zero instance count means all
+/// in the instance buffer.
+fn gpu_draw(
+    system: &mut System,
+    buffer: u32,
+    instance_buffer: u32,
+    instance_count: u32,
+    range: GpuDrawRange,
+) -> Result<(), RuntimeError> {
     taca_gpu_ensure_render_pass(system);
     update_buffers(system, false);
-    let buffer = system.gpu.buffers[buffer as usize - 1].lock().unwrap();
+    let drawn = buffer;
+    let buffer = system.gpu.buffers[buffer as usize - 1].clone();
+    let instance = match instance_buffer {
+        0 => None,
+        _ => Some(system.gpu.buffers[instance_buffer as usize - 1].clone()),
+    };
+    // Slots come from step modes, so mixups would only fail in validation.
+    let step_mode = |buffer: &Arc<Mutex<GpuBuffer>>| match &buffer.lock().unwrap().detail {
+        GpuBufferDetail::Vertex { layout } => Some(layout.step_mode),
+        _ => None,
+    };
+    let vertex = match &buffer.lock().unwrap().detail {
+        GpuBufferDetail::Index { vertex, .. } => vertex.clone(),
+        _ => buffer.clone(),
+    };
+    if step_mode(&vertex) != Some(native::WGPUVertexStepMode_Vertex) {
+        return Err(RuntimeError::new(format!(
+            "Drawn buffer {drawn} needs per vertex steps"
+        )));
+    }
+    if let Some(instance) = &instance {
+        if step_mode(instance) != Some(native::WGPUVertexStepMode_Instance) {
+            return Err(RuntimeError::new(format!(
+                "Instance buffer {instance_buffer} needs per instance steps"
+            )));
+        }
+    }
+    let pipeline = ensure_render_pipeline(system, &buffer, instance.as_ref());
+    let bind_group = ensure_target_bind_group(system);
+    let buffer = buffer.lock().unwrap();
     gpu_draw_set_buffer(system, &buffer);
+    let instance_count = match instance {
+        Some(instance) => {
+            let instance = instance.lock().unwrap();
+            gpu_draw_set_buffer(system, &instance);
+            let layout =
+                extract_enum_value!(&instance.detail, GpuBufferDetail::Vertex { layout } => layout);
+            match instance_count {
+                0 => (instance.size / layout.array_stride as usize) as u32,
+                _ => instance_count,
+            }
+        }
+        None => instance_count,
+    };
     unsafe {
+        wgpu_native::command::wgpuRenderPassEncoderSetPipeline(system.render_pass.0, pipeline);
         wgpu_native::command::wgpuRenderPassEncoderSetBindGroup(
             system.render_pass.0,
             0,
-            system.gpu.bind_group.0,
+            bind_group,
             0,
             null(),
         );
     }
     match &buffer.detail {
-        GpuBufferDetail::Index { format, vertex } => {
-            let vertex = vertex.lock().unwrap();
-            gpu_draw_set_buffer(system, &vertex);
+        GpuBufferDetail::Index { format, .. } => {
+            let index_size = match *format {
+                native::WGPUIndexFormat_Uint16 => 2,
+                native::WGPUIndexFormat_Uint32 => 4,
+                _ => panic!(),
+            };
+            let count = range.count_in(buffer.size as u32 / index_size)?;
             unsafe {
                 wgpu_native::command::wgpuRenderPassEncoderDrawIndexed(
                     system.render_pass.0,
-                    buffer.size as u32
-                        / match *format {
-                            native::WGPUIndexFormat_Uint16 => 2,
-                            native::WGPUIndexFormat_Uint32 => 4,
-                            _ => panic!(),
-                        },
-                    1,
-                    0,
+                    count,
+                    instance_count,
+                    range.first,
+                    range.base_vertex,
                     0,
+                );
+            }
+        }
+        GpuBufferDetail::Vertex { layout } => {
+            let count = range.count_in((buffer.size / layout.array_stride as usize) as u32)?;
+            unsafe {
+                wgpu_native::command::wgpuRenderPassEncoderDraw(
+                    system.render_pass.0,
+                    count,
+                    instance_count,
+                    range.first,
                     0,
                 );
             }
         }
-        GpuBufferDetail::Vertex { layout } => unsafe {
-            wgpu_native::command::wgpuRenderPassEncoderDraw(
-                system.render_pass.0,
-                (buffer.size / layout.array_stride as usize) as u32,
-                1,
-                0,
-                0,
-            );
-        },
-        _ => {}
+        _ => {}
+    }
+    Ok(())
+}
+
+/// taca_gpu_indexBufferCreate
+pub fn taca_gpu_index_buffer_create(
+    mut env: FunctionEnvMut<System>,
+    size: u32,
+    data: u32,
+    format: u32,
+    vertex: u32,
+) -> u32 {
+    let (system, store) = env.data_and_store_mut();
+    let view = system.memory.as_ref().unwrap().view(&store);
+    let vertex = system.gpu.buffers[vertex as usize - 1].clone();
+    assert!(matches!(
+        vertex.lock().unwrap().detail,
+        GpuBufferDetail::Vertex { .. },
+    ));
+    system.gpu.buffers.push(Arc::new(Mutex::new(GpuBuffer {
+        buffer: Default::default(),
+        data: WasmPtr::<u8>::new(data)
+            .slice(&view, size)
+            .unwrap()
+            .read_to_vec()
+            .unwrap(),
+        detail: GpuBufferDetail::Index { format, vertex },
+        size: 0,
+        dirty: None,
+    })));
+    system.gpu.buffers.len() as u32
+}
+
+// taca_EXPORT void taca_gpuPresent(void);
+pub fn taca_gpu_present(mut env: FunctionEnvMut<System>) -> Result<(), RuntimeError> {
+    let system = env.data_mut();
+    if system.gpu.target != GpuTarget::default() {
+        end_render_pass(system);
+        system.gpu.target = Default::default();
+    }
+    taca_gpu_ensure_render_pass(system);
+    end_render_pass(system);
+    system.gpu.frame_begun = false;
+    system.gpu.targets_begun.clear();
+    if system.gpu.blit.needed {
+        blit_frame_texture(system);
+    }
+    if !system.gpu.render_texture_view.0.is_null() {
+        unsafe {
+            wgpu_native::device::wgpuTextureViewDrop(system.gpu.render_texture_view.0);
+            system.gpu.render_texture_view.0 = null_mut();
+        }
+    }
+    wgpu_ensure_command_encoder_finish_simple(system);
+    wgpu_ensure_queue_submit_simple(system);
+    wgpu_swap_chain_present_simple(system)
+}
+
+/// taca_gpu_clear
+pub fn taca_gpu_clear(mut env: FunctionEnvMut<System>, color: u32) {
+    let (system, store) = env.data_and_store_mut();
+    let view = system.memory.as_ref().unwrap().view(&store);
+    let frame = &mut system.gpu.frame;
+    match color {
+        0 => frame.load_color = true,
+        _ => {
+            let color = WasmPtr::<WasmWGPUColor>::new(color).read(&view).unwrap();
+            frame.clear_color = color.to_native();
+            frame.load_color = false;
+        }
+    }
+}
+
+/// taca_gpu_frameOptions
+pub fn taca_gpu_frame_options(mut env: FunctionEnvMut<System>, options: u32) {
+    let (system, store) = env.data_and_store_mut();
+    let view = system.memory.as_ref().unwrap().view(&store);
+    system.gpu.frame = match options {
+        0 => Default::default(),
+        _ => {
+            let options = WasmPtr::<WasmGpuFrameOptions>::new(options)
+                .read(&view)
+                .unwrap();
+            GpuFrameOptions {
+                clear_color: options.clear_color.to_native(),
+                clear_depth: options.clear_depth,
+                load_color: options.load_color,
+                load_depth: options.load_depth,
+            }
+        }
+    };
+}
+
+/// taca_gpu_blendModeUse
+pub fn taca_gpu_blend_mode_use(
+    mut env: FunctionEnvMut<System>,
+    mode: u32,
+) -> Result<(), RuntimeError> {
+    let system = env.data_mut();
+    system.gpu.blend_mode = GpuBlendMode::from_u32(mode)?;
+    Ok(())
+}
+
+/// taca_gpu_pipelineCreate
+pub fn taca_gpu_pipeline_create(
+    mut env: FunctionEnvMut<System>,
+    shader: u32,
+    info: u32,
+) -> Result<u32, RuntimeError> {
+    let (system, store) = env.data_and_store_mut();
+    let view = system.memory.as_ref().unwrap().view(&store);
+    let shader = (shader as usize)
+        .checked_sub(1)
+        .filter(|&index| index < system.gpu.shaders.len())
+        .ok_or_else(|| RuntimeError::new(format!("Invalid shader: {shader}")))?;
+    let mut pipeline = GpuPipeline {
+        shader,
+        ..Default::default()
+    };
+    if info != 0 {
+        let info = WasmPtr::<WasmGpuPipelineInfo>::new(info)
+            .read(&view)
+            .map_err(|err| RuntimeError::new(err.to_string()))?;
+        if info.vertex_entry != 0 {
+            pipeline.vertex_entry = read_cstring(WasmPtr::<u8>::new(info.vertex_entry), &view)?;
+        }
+        if info.fragment_entry != 0 {
+            pipeline.fragment_entry = read_cstring(WasmPtr::<u8>::new(info.fragment_entry), &view)?;
+        }
+        pipeline.topology = topology(info.topology)?;
+        pipeline.cull_mode = info.cull_mode;
+        pipeline.front_face = info.front_face;
+        if info.depth_compare != native::WGPUCompareFunction_Undefined {
+            pipeline.depth_compare = info.depth_compare;
+        }
+        pipeline.depth_write = !info.depth_read_only;
+        pipeline.blend = match info.blend {
+            0 => GpuBlendMode::from_u32(info.blend_mode)?.blend_state(),
+            blend => read_blend_state(blend, &view),
+        };
+        if info.use_write_mask {
+            pipeline.write_mask = info.write_mask;
+        }
+    }
+    system.gpu.pipelines.push(pipeline);
+    Ok(system.gpu.pipelines.len() as u32)
+}
+
+/// taca_gpu_pipelineUse
+pub fn taca_gpu_pipeline_use(
+    mut env: FunctionEnvMut<System>,
+    pipeline: u32,
+) -> Result<(), RuntimeError> {
+    let system = env.data_mut();
+    if pipeline as usize > system.gpu.pipelines.len() {
+        return Err(RuntimeError::new(format!("Invalid pipeline: {pipeline}")));
+    }
+    system.gpu.pipeline = pipeline;
+    Ok(())
+}
+
+/// taca_gpu_renderTargetCreate
+pub fn taca_gpu_render_target_create(
+    mut env: FunctionEnvMut<System>,
+    info: u32,
+) -> Result<u32, RuntimeError> {
+    let (system, store) = env.data_and_store_mut();
+    let view = system.memory.as_ref().unwrap().view(&store);
+    let info = WasmPtr::<WasmGpuTextureInfo>::new(info)
+        .read(&view)
+        .map_err(|err| RuntimeError::new(err.to_string()))?;
+    if !render_target_format_supported(info.format) {
+        return Err(RuntimeError::new(format!(
+            "Unsupported render target format: {}",
+            info.format
+        )));
+    }
+    if info.width == 0 || info.height == 0 {
+        return Err(RuntimeError::new("Texture size can't be zero"));
+    }
+    let descriptor = native::WGPUTextureDescriptor {
+        nextInChain: null(),
+        label: null(),
+        usage: native::WGPUTextureUsage_RenderAttachment | native::WGPUTextureUsage_TextureBinding,
+        dimension: native::WGPUTextureDimension_2D,
+        size: native::WGPUExtent3D {
+            width: info.width,
+            height: info.height,
+            depthOrArrayLayers: 1,
+        },
+        format: info.format,
+        mipLevelCount: 1,
+        sampleCount: 1,
+        viewFormatCount: 0,
+        viewFormats: null(),
+    };
+    // Only ever drawn, so no levels to upload.
+    system.gpu.textures.push(GpuTexture {
+        binding: info.binding,
+        descriptor: WGPUTextureDescriptor(descriptor),
+        generate_mips: false,
+        levels: vec![],
+        storage: false,
+        texture: Default::default(),
+        texture_view: None,
+    });
+    Ok(system.gpu.textures.len() as u32)
+}
+
+/// Uncompressed color formats can all be drawn to, as can depth.
+fn render_target_format_supported(format: native::WGPUTextureFormat) -> bool {
+    matches!(texture_block(format), Some((1, 1, _)))
+        || texture_sample_type(format) == native::WGPUTextureSampleType_Depth
+}
+
+/// taca_gpu_renderTargetUse
+pub fn taca_gpu_render_target_use(
+    mut env: FunctionEnvMut<System>,
+    color: u32,
+    depth: u32,
+) -> Result<(), RuntimeError> {
+    let system = env.data_mut();
+    let target = GpuTarget { color, depth };
+    check_render_target(&system.gpu.textures, target)?;
+    if target != system.gpu.target {
+        end_render_pass(system);
+        system.gpu.target = target;
+    }
+    Ok(())
+}
+
+/// Attachments need render targets of the right kind for each slot, and of
+/// matching sizes.
+fn check_render_target(textures: &[GpuTexture], target: GpuTarget) -> Result<(), RuntimeError> {
+    let descriptor = |texture: u32, depth: bool| match texture {
+        0 => Ok(None),
+        _ => {
+            let descriptor = &(texture as usize)
+                .checked_sub(1)
+                .and_then(|index| textures.get(index))
+                .ok_or_else(|| RuntimeError::new(format!("Invalid texture: {texture}")))?
+                .descriptor
+                .0;
+            if descriptor.usage & native::WGPUTextureUsage_RenderAttachment == 0 {
+                return Err(RuntimeError::new(format!(
+                    "Texture {texture} isn't a render target"
+                )));
+            }
+            let is_depth =
+                texture_sample_type(descriptor.format) == native::WGPUTextureSampleType_Depth;
+            if is_depth != depth {
+                let slot = if depth { "depth" } else { "color" };
+                return Err(RuntimeError::new(format!(
+                    "Texture {texture} has the wrong format for {slot}: {}",
+                    descriptor.format
+                )));
+            }
+            Ok(Some(descriptor.size))
+        }
+    };
+    let color = descriptor(target.color, false)?;
+    let depth = descriptor(target.depth, true)?;
+    if let (Some(color), Some(depth)) = (color, depth) {
+        if (color.width, color.height) != (depth.width, depth.height) {
+            return Err(RuntimeError::new(format!(
+                "Color target {}x{} doesn't match depth target {}x{}",
+                color.width, color.height, depth.width, depth.height
+            )));
+        }
     }
+    Ok(())
 }
 
-/// taca_gpu_indexBufferCreate
-pub fn taca_gpu_index_buffer_create(
+/// taca_gpu_samplerCreate
+pub fn taca_gpu_sampler_create(
     mut env: FunctionEnvMut<System>,
-    size: u32,
-    data: u32,
-    format: u32,
-    vertex: u32,
+    binding: u32,
+    descriptor: u32,
 ) -> u32 {
     let (system, store) = env.data_and_store_mut();
     let view = system.memory.as_ref().unwrap().view(&store);
-    let vertex = system.gpu.buffers[vertex as usize - 1].clone();
-    assert!(matches!(
-        vertex.lock().unwrap().detail,
-        GpuBufferDetail::Vertex { .. },
-    ));
-    system.gpu.buffers.push(Arc::new(Mutex::new(GpuBuffer {
-        buffer: Default::default(),
-        data: WasmPtr::<u8>::new(data)
-            .slice(&view, size)
-            .unwrap()
-            .read_to_vec()
+    let descriptor = match descriptor {
+        0 => WasmWGPUSamplerDescriptor::linear(),
+        _ => WasmPtr::<WasmWGPUSamplerDescriptor>::new(descriptor)
+            .read(&view)
             .unwrap(),
-        detail: GpuBufferDetail::Index { format, vertex },
-        size: 0,
-        written: false,
-    })));
-    system.gpu.buffers.len() as u32
-}
-
-// taca_EXPORT void taca_gpuPresent(void);
-pub fn taca_gpu_present(mut env: FunctionEnvMut<System>) {
-    let system = env.data_mut();
-    taca_gpu_ensure_render_pass(system);
-    unsafe {
-        wgpu_native::command::wgpuRenderPassEncoderEnd(system.render_pass.0);
-    }
-    system.render_pass.0 = null_mut();
-    if !system.gpu.render_texture_view.0.is_null() {
-        unsafe {
-            wgpu_native::device::wgpuTextureViewDrop(system.gpu.render_texture_view.0);
-            system.gpu.render_texture_view.0 = null_mut();
-        }
-    }
-    wgpu_ensure_command_encoder_finish_simple(system);
-    wgpu_ensure_queue_submit_simple(system);
-    unsafe {
-        wgpu_native::device::wgpuSwapChainPresent(system.swap_chain.0);
-    }
+    };
+    system.gpu.samplers.push(GpuSampler {
+        binding,
+        descriptor,
+        sampler: Default::default(),
+    });
+    system.gpu.samplers.len() as u32
 }
 
 pub fn taca_gpu_shader_create(mut env: FunctionEnvMut<System>, wgsl: u32) -> u32 {
     let (system, store) = env.data_and_store_mut();
     let view = system.memory.as_ref().unwrap().view(&store);
     let wgsl = read_cstring(WasmPtr::<u8>::new(wgsl), &view).unwrap();
-    system.gpu.shaders.push(wgsl);
+    system.gpu.shaders.push(GpuShader { module: 0, wgsl });
     // wgpu_device_create_shader_module_simple(system, &store, WasmPtr::<u8>::new(wgsl))
     system.gpu.shaders.len() as u32
 }
@@ -907,52 +2659,474 @@ pub fn taca_gpu_uniform_buffer_create(
         data: vec![0; size as usize],
         detail: GpuBufferDetail::Uniform { binding },
         size: 0,
-        written: false,
+        dirty: None,
+    })));
+    system.gpu.buffers.len() as u32
+}
+
+/// taca_gpu_storageBufferCreate
+pub fn taca_gpu_storage_buffer_create(
+    mut env: FunctionEnvMut<System>,
+    size: u32,
+    binding: u32,
+    read_only: u32,
+) -> u32 {
+    let system = env.data_mut();
+    system.gpu.buffers.push(Arc::new(Mutex::new(GpuBuffer {
+        buffer: Default::default(),
+        data: vec![0; size as usize],
+        detail: GpuBufferDetail::Storage {
+            binding,
+            read_only: read_only != 0,
+        },
+        size: 0,
+        dirty: None,
     })));
     system.gpu.buffers.len() as u32
 }
 
-pub fn taca_gpu_texture_create(mut env: FunctionEnvMut<System>, data: u32, info: u32) -> u32 {
+pub fn taca_gpu_texture_create(
+    env: FunctionEnvMut<System>,
+    data: u32,
+    info: u32,
+) -> Result<u32, RuntimeError> {
+    gpu_texture_create(env, data, info, false, false)
+}
+
+/// taca_gpu_textureCreateEx
+pub fn taca_gpu_texture_create_ex(
+    env: FunctionEnvMut<System>,
+    data: u32,
+    detail: u32,
+) -> Result<u32, RuntimeError> {
+    gpu_texture_create(env, data, detail, true, false)
+}
+
+/// taca_gpu_textureFormatSupported
+pub fn taca_gpu_texture_format_supported(mut env: FunctionEnvMut<System>, format: u32) -> u32 {
+    texture_format_supported(env.data_mut(), format) as u32
+}
+
+/// Devices request compression features whenever the adapter has them.
+fn texture_format_supported(system: &mut System, format: native::WGPUTextureFormat) -> bool {
+    let feature = match texture_format_feature(format) {
+        Some(feature) => feature,
+        None => return texture_block(format).is_some(),
+    };
+    if !system.device.0.is_null() {
+        return unsafe { wgpu_native::device::wgpuDeviceHasFeature(system.device.0, feature) };
+    }
+    wgpu_ensure_instance_simple(system);
+    wgpu_instance_ensure_surface_simple(system);
+    wgpu_instance_ensure_adapter_simple(system);
+    unsafe { wgpu_native::device::wgpuAdapterHasFeature(system.adapter.0, feature) }
+}
+
+/// taca_gpu_textureWrite
+pub fn taca_gpu_texture_write(
+    mut env: FunctionEnvMut<System>,
+    texture: u32,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    data: u32,
+) -> Result<(), RuntimeError> {
     let (system, store) = env.data_and_store_mut();
     let view = system.memory.as_ref().unwrap().view(&store);
-    let info = WasmPtr::<WasmGpuTextureInfo>::new(info)
-        .read(&view)
-        .unwrap();
+    let texture = level_texture(&mut system.gpu.textures, texture)?;
+    let descriptor = &texture.descriptor.0;
+    let format = descriptor.format;
+    let (block_width, block_height, block_size) = texture_block(format)
+        .ok_or_else(|| RuntimeError::new(format!("Unsupported texture format: {format}")))?;
+    check_texture_region(
+        descriptor.size,
+        (block_width, block_height),
+        x,
+        y,
+        width,
+        height,
+    )?;
+    // Empty rows would otherwise split the data into nothing.
+    if width == 0 || height == 0 {
+        return Ok(());
+    }
+    let (bytes_per_row, rows) = texture_pitch(format, width, height).unwrap();
+    let data = WasmPtr::<u8>::new(data)
+        .slice(&view, bytes_per_row * rows)
+        .and_then(|slice| slice.read_to_vec())
+        .map_err(|err| RuntimeError::new(err.to_string()))?;
+    // Keep the local copy current for new devices and mip generation.
+    let full_bytes_per_row = texture_pitch(format, descriptor.size.width, 1).unwrap().0 as usize;
+    let start =
+        (y / block_height) as usize * full_bytes_per_row + (x / block_width * block_size) as usize;
+    for (row, source) in data.chunks(bytes_per_row as usize).enumerate() {
+        let start = start + row * full_bytes_per_row;
+        texture.levels[0][start..start + source.len()].copy_from_slice(source);
+    }
+    if texture.texture.0.is_null() {
+        return Ok(());
+    }
+    if texture.generate_mips {
+        generate_mips(texture);
+        for (level, data) in texture.levels.iter().enumerate() {
+            write_texture_level(system.queue.0, texture, level as u32, data);
+        }
+        return Ok(());
+    }
+    unsafe {
+        wgpu_native::device::wgpuQueueWriteTexture(
+            system.queue.0,
+            Some(&native::WGPUImageCopyTexture {
+                nextInChain: null(),
+                texture: texture.texture.0,
+                mipLevel: 0,
+                origin: native::WGPUOrigin3D { x, y, z: 0 },
+                aspect: 0,
+            }),
+            data.as_ptr(),
+            data.len(),
+            Some(&native::WGPUTextureDataLayout {
+                nextInChain: null(),
+                offset: 0,
+                bytesPerRow: bytes_per_row,
+                rowsPerImage: rows,
+            }),
+            Some(&native::WGPUExtent3D {
+                width: width.next_multiple_of(block_width),
+                height: height.next_multiple_of(block_height),
+                depthOrArrayLayers: 1,
+            }),
+        );
+    }
+    Ok(())
+}
+
+/// taca_gpu_textureWriteMip
+pub fn taca_gpu_texture_write_mip(
+    mut env: FunctionEnvMut<System>,
+    texture: u32,
+    level: u32,
+    data: u32,
+) -> Result<(), RuntimeError> {
+    let (system, store) = env.data_and_store_mut();
+    let view = system.memory.as_ref().unwrap().view(&store);
+    let texture = level_texture(&mut system.gpu.textures, texture)?;
+    let count = texture.levels.len();
+    let level_data = texture
+        .levels
+        .get_mut(level as usize)
+        .ok_or_else(|| RuntimeError::new(format!("Invalid mip level {level} of {count}")))?;
+    WasmPtr::<u8>::new(data)
+        .slice(&view, level_data.len() as u32)
+        .and_then(|slice| slice.read_slice(level_data))
+        .map_err(|err| RuntimeError::new(err.to_string()))?;
+    let mut levels = level..level + 1;
+    if level == 0 && texture.generate_mips {
+        generate_mips(texture);
+        levels.end = texture.levels.len() as u32;
+    }
+    // Otherwise uploaded along with everything else once there's a device.
+    if !texture.texture.0.is_null() {
+        for level in levels {
+            write_texture_level(
+                system.queue.0,
+                texture,
+                level,
+                &texture.levels[level as usize],
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Regions must fit in the texture and align to blocks, except for partial
+/// blocks at the right and bottom edges.
+fn check_texture_region(
+    size: native::WGPUExtent3D,
+    (block_width, block_height): (u32, u32),
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> Result<(), RuntimeError> {
+    let right = x.checked_add(width).filter(|&right| right <= size.width);
+    let bottom = y
+        .checked_add(height)
+        .filter(|&bottom| bottom <= size.height);
+    let (Some(right), Some(bottom)) = (right, bottom) else {
+        return Err(RuntimeError::new(format!(
+            "Region {width}x{height} at {x},{y} is outside {}x{} texture",
+            size.width, size.height
+        )));
+    };
+    let aligned = x % block_width == 0
+        && y % block_height == 0
+        && (width % block_width == 0 || right == size.width)
+        && (height % block_height == 0 || bottom == size.height);
+    match aligned {
+        true => Ok(()),
+        false => Err(RuntimeError::new(format!(
+            "Region {width}x{height} at {x},{y} must align to {block_width}x{block_height} blocks"
+        ))),
+    }
+}
+
+/// Textures with host copies of their levels, which render targets lack.
+fn level_texture(
+    textures: &mut [GpuTexture],
+    texture: u32,
+) -> Result<&mut GpuTexture, RuntimeError> {
+    let texture = (texture as usize)
+        .checked_sub(1)
+        .and_then(|index| textures.get_mut(index))
+        .ok_or_else(|| RuntimeError::new(format!("Invalid texture: {texture}")))?;
+    match texture.levels.is_empty() {
+        true => Err(RuntimeError::new("Can't write render targets")),
+        false => Ok(texture),
+    }
+}
+
+/// taca_gpu_storageTextureCreate
+pub fn taca_gpu_storage_texture_create(
+    env: FunctionEnvMut<System>,
+    info: u32,
+) -> Result<u32, RuntimeError> {
+    gpu_texture_create(env, 0, info, false, true)
+}
+
+/// Base levels need whole blocks, though smaller mip levels can end partway.
+fn check_texture_size(
+    format: native::WGPUTextureFormat,
+    width: u32,
+    height: u32,
+) -> Result<(), RuntimeError> {
+    if width == 0 || height == 0 {
+        return Err(RuntimeError::new("Texture size can't be zero"));
+    }
+    let (block_width, block_height, _) = texture_block(format)
+        .ok_or_else(|| RuntimeError::new(format!("Unsupported texture format: {format}")))?;
+    if width % block_width != 0 || height % block_height != 0 {
+        return Err(RuntimeError::new(format!(
+            "Texture size {width}x{height} must be whole {block_width}x{block_height} blocks"
+        )));
+    }
+    Ok(())
+}
+
+/// Zero means a full chain down to 1x1.
+fn mip_level_count(count: u32, width: u32, height: u32) -> Result<u32, RuntimeError> {
+    let max_level_count = u32::BITS - width.max(height).leading_zeros();
+    match count {
+        0 => Ok(max_level_count),
+        count if count > max_level_count => Err(RuntimeError::new(format!(
+            "Mip level count {count} exceeds {max_level_count} for {width}x{height}"
+        ))),
+        count => Ok(count),
+    }
+}
+
+/// Reads either info or detail, where null data starts zeroed.
+fn gpu_texture_create(
+    mut env: FunctionEnvMut<System>,
+    data: u32,
+    info: u32,
+    detailed: bool,
+    storage: bool,
+) -> Result<u32, RuntimeError> {
+    let (system, store) = env.data_and_store_mut();
+    let view = system.memory.as_ref().unwrap().view(&store);
+    let detail = match detailed {
+        true => WasmPtr::<WasmGpuTextureDetail>::new(info)
+            .read(&view)
+            .unwrap(),
+        false => {
+            let info = WasmPtr::<WasmGpuTextureInfo>::new(info)
+                .read(&view)
+                .unwrap();
+            WasmGpuTextureDetail {
+                format: info.format,
+                binding: info.binding,
+                width: info.width,
+                height: info.height,
+                mip_level_count: 1,
+                generate_mips: false,
+            }
+        }
+    };
+    if !texture_format_supported(system, detail.format) {
+        return Err(RuntimeError::new(format!(
+            "Unsupported texture format: {}",
+            detail.format
+        )));
+    }
+    check_texture_size(detail.format, detail.width, detail.height)?;
+    // Later levels and regions are smaller, so their sizes fit as well.
+    texture_data_size(detail.format, detail.width, detail.height)?;
+    if detail.generate_mips && mip_channels(detail.format).is_none() {
+        return Err(RuntimeError::new(format!(
+            "Can't generate mips for texture format: {}",
+            detail.format
+        )));
+    }
+    let mip_level_count = match storage {
+        // Storage binding needs a single level.
+        true => 1,
+        false => mip_level_count(detail.mip_level_count, detail.width, detail.height)?,
+    };
     let descriptor = native::WGPUTextureDescriptor {
         nextInChain: null(),
         label: null(),
-        usage: native::WGPUTextureUsage_CopyDst | native::WGPUTextureUsage_TextureBinding,
+        usage: native::WGPUTextureUsage_CopyDst
+            | native::WGPUTextureUsage_TextureBinding
+            | match storage {
+                true => native::WGPUTextureUsage_StorageBinding,
+                false => 0,
+            },
         dimension: native::WGPUTextureDimension_2D,
         size: native::WGPUExtent3D {
-            width: info.width,
-            height: info.height,
+            width: detail.width,
+            height: detail.height,
             depthOrArrayLayers: 1,
         },
-        format: info.format,
-        mipLevelCount: 1,
+        format: detail.format,
+        mipLevelCount: mip_level_count,
         sampleCount: 1,
         viewFormatCount: 0,
         viewFormats: null(),
     };
-    let size = info.width * info.height * bytes_per_pixel(info.format);
-    let data = WasmPtr::<u8>::new(data)
-        .slice(&view, size)
-        .unwrap()
-        .read_to_vec()
-        .unwrap();
-    system.gpu.textures.push(GpuTexture {
-        binding: info.binding,
-        data,
+    let levels = (0..mip_level_count)
+        .map(|level| {
+            let size = mip_size(descriptor.size, level);
+            let len = texture_data_size(detail.format, size.width, size.height).unwrap();
+            vec![0; len as usize]
+        })
+        .collect();
+    let mut texture = GpuTexture {
+        binding: detail.binding,
         descriptor: WGPUTextureDescriptor(descriptor),
+        generate_mips: detail.generate_mips,
+        levels,
+        storage,
+        texture: Default::default(),
         texture_view: None,
-    });
-    system.gpu.textures.len() as u32
+    };
+    if data != 0 {
+        WasmPtr::<u8>::new(data)
+            .slice(&view, texture.levels[0].len() as u32)
+            .and_then(|slice| slice.read_slice(&mut texture.levels[0]))
+            .map_err(|err| RuntimeError::new(err.to_string()))?;
+        if texture.generate_mips {
+            generate_mips(&mut texture);
+        }
+    }
+    system.gpu.textures.push(texture);
+    Ok(system.gpu.textures.len() as u32)
+}
+
+/// Width, height, and bytes of each texel block, which is a single pixel for
+/// uncompressed formats.
+fn texture_block(format: native::WGPUTextureFormat) -> Option<(u32, u32, u32)> {
+    let block = match format {
+        native::WGPUTextureFormat_R8Unorm => (1, 1, 1),
+        native::WGPUTextureFormat_RG8Unorm | native::WGPUTextureFormat_R16Float => (1, 1, 2),
+        native::WGPUTextureFormat_RGBA8Unorm
+        | native::WGPUTextureFormat_RGBA8UnormSrgb
+        | native::WGPUTextureFormat_BGRA8Unorm
+        | native::WGPUTextureFormat_BGRA8UnormSrgb
+        | native::WGPUTextureFormat_RG16Float
+        | native::WGPUTextureFormat_R32Float => (1, 1, 4),
+        native::WGPUTextureFormat_RGBA16Float | native::WGPUTextureFormat_RG32Float => (1, 1, 8),
+        native::WGPUTextureFormat_RGBA32Float => (1, 1, 16),
+        native::WGPUTextureFormat_BC1RGBAUnorm
+        | native::WGPUTextureFormat_BC1RGBAUnormSrgb
+        | native::WGPUTextureFormat_BC4RUnorm
+        | native::WGPUTextureFormat_BC4RSnorm
+        | native::WGPUTextureFormat_ETC2RGB8Unorm
+        | native::WGPUTextureFormat_ETC2RGB8UnormSrgb
+        | native::WGPUTextureFormat_ETC2RGB8A1Unorm
+        | native::WGPUTextureFormat_ETC2RGB8A1UnormSrgb
+        | native::WGPUTextureFormat_EACR11Unorm
+        | native::WGPUTextureFormat_EACR11Snorm => (4, 4, 8),
+        native::WGPUTextureFormat_BC1RGBAUnorm..=native::WGPUTextureFormat_BC7RGBAUnormSrgb
+        | native::WGPUTextureFormat_ETC2RGB8Unorm..=native::WGPUTextureFormat_EACRG11Snorm => {
+            (4, 4, 16)
+        }
+        native::WGPUTextureFormat_ASTC4x4Unorm..=native::WGPUTextureFormat_ASTC12x12UnormSrgb => {
+            // Each size has Unorm then UnormSrgb.
+            let (width, height) =
+                ASTC_BLOCKS[((format - native::WGPUTextureFormat_ASTC4x4Unorm) / 2) as usize];
+            (width, height, 16)
+        }
+        _ => return None,
+    };
+    Some(block)
+}
+
+const ASTC_BLOCKS: [(u32, u32); 14] = [
+    (4, 4),
+    (5, 4),
+    (5, 5),
+    (6, 5),
+    (6, 6),
+    (8, 5),
+    (8, 6),
+    (8, 8),
+    (10, 5),
+    (10, 6),
+    (10, 8),
+    (10, 10),
+    (12, 10),
+    (12, 12),
+];
+
+/// Bytes per row of blocks and number of block rows, or none for unsupported
+/// formats or rows too large to count.
+fn texture_pitch(format: native::WGPUTextureFormat, width: u32, height: u32) -> Option<(u32, u32)> {
+    let (block_width, block_height, block_size) = texture_block(format)?;
+    Some((
+        width.div_ceil(block_width).checked_mul(block_size)?,
+        height.div_ceil(block_height),
+    ))
+}
+
+/// Bytes of tightly packed blocks, which guest memory needs to be able to hold.
+fn texture_data_size(
+    format: native::WGPUTextureFormat,
+    width: u32,
+    height: u32,
+) -> Result<u32, RuntimeError> {
+    texture_pitch(format, width, height)
+        .and_then(|(bytes_per_row, rows)| bytes_per_row.checked_mul(rows))
+        .ok_or_else(|| RuntimeError::new(format!("Texture data too large for {width}x{height}")))
+}
+
+/// Compressed formats need device features.
+fn texture_format_feature(format: native::WGPUTextureFormat) -> Option<native::WGPUFeatureName> {
+    match format {
+        native::WGPUTextureFormat_BC1RGBAUnorm..=native::WGPUTextureFormat_BC7RGBAUnormSrgb => {
+            Some(native::WGPUFeatureName_TextureCompressionBC)
+        }
+        native::WGPUTextureFormat_ETC2RGB8Unorm..=native::WGPUTextureFormat_EACRG11Snorm => {
+            Some(native::WGPUFeatureName_TextureCompressionETC2)
+        }
+        native::WGPUTextureFormat_ASTC4x4Unorm..=native::WGPUTextureFormat_ASTC12x12UnormSrgb => {
+            Some(native::WGPUFeatureName_TextureCompressionASTC)
+        }
+        _ => None,
+    }
 }
 
-fn bytes_per_pixel(format: native::WGPUTextureFormat) -> u32 {
+/// 32-bit floats can't be filtered without an optional feature.
+fn texture_sample_type(format: native::WGPUTextureFormat) -> native::WGPUTextureSampleType {
     match format {
-        native::WGPUTextureFormat_RGBA8Unorm => 4,
-        _ => todo!(),
+        native::WGPUTextureFormat_R32Float
+        | native::WGPUTextureFormat_RG32Float
+        | native::WGPUTextureFormat_RGBA32Float => native::WGPUTextureSampleType_UnfilterableFloat,
+        native::WGPUTextureFormat_Depth16Unorm
+        | native::WGPUTextureFormat_Depth24Plus
+        | native::WGPUTextureFormat_Depth32Float => native::WGPUTextureSampleType_Depth,
+        _ => native::WGPUTextureSampleType_Float,
     }
 }
 
@@ -962,7 +3136,7 @@ pub fn taca_gpu_vertex_buffer_create(
     size: u32,
     data: u32,
     layout: u32,
-) -> u32 {
+) -> Result<u32, RuntimeError> {
     assert_ne!(data, 0);
     assert_ne!(layout, 0);
     let (system, store) = env.data_and_store_mut();
@@ -977,18 +3151,392 @@ pub fn taca_gpu_vertex_buffer_create(
     let layout = WasmPtr::<WasmWGPUVertexBufferLayout>::new(layout)
         .read(&view)
         .unwrap();
+    // Draws divide by the stride to count vertices or instances.
+    if layout.array_stride == 0 {
+        return Err(RuntimeError::new("Vertex buffer layouts need a stride"));
+    }
     let layout = WgpuVertexBufferLayout {
         array_stride: layout.array_stride,
         step_mode: layout.step_mode,
         attributes: layout.attributes_vec(&view),
-        slot: 0,
+        // Instance data goes alongside the vertex data of each draw.
+        slot: match layout.step_mode {
+            native::WGPUVertexStepMode_Instance => 1,
+            _ => 0,
+        },
     };
     system.gpu.buffers.push(Arc::new(Mutex::new(GpuBuffer {
         buffer: Default::default(),
         data,
         detail: GpuBufferDetail::Vertex { layout },
         size: 0,
-        written: false,
+        dirty: None,
     })));
-    system.gpu.buffers.len() as u32
+    Ok(system.gpu.buffers.len() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topologies() {
+        assert_eq!(
+            topology(0).unwrap(),
+            native::WGPUPrimitiveTopology_TriangleList
+        );
+        assert_eq!(
+            topology(3).unwrap(),
+            native::WGPUPrimitiveTopology_LineStrip
+        );
+        assert_eq!(
+            topology(4).unwrap(),
+            native::WGPUPrimitiveTopology_PointList
+        );
+        assert!(topology(5).is_err());
+    }
+
+    #[test]
+    fn blend_modes() {
+        assert_eq!(GpuBlendMode::from_u32(0).unwrap(), GpuBlendMode::Default);
+        assert_eq!(GpuBlendMode::from_u32(5).unwrap(), GpuBlendMode::Multiply);
+        assert!(GpuBlendMode::from_u32(6).is_err());
+        assert!(GpuBlendMode::Opaque.blend_state().is_none());
+        let alpha = GpuBlendMode::Alpha.blend_state().unwrap();
+        assert_eq!(alpha.color.srcFactor, native::WGPUBlendFactor_SrcAlpha);
+        assert_eq!(alpha.alpha.srcFactor, native::WGPUBlendFactor_One);
+        let multiply = GpuBlendMode::Multiply.blend_state().unwrap();
+        assert_eq!(multiply.color.srcFactor, native::WGPUBlendFactor_Dst);
+        assert_eq!(multiply.alpha.dstFactor, native::WGPUBlendFactor_One);
+    }
+
+    #[test]
+    fn write_ranges() {
+        assert_eq!(write_range(0, 8, 8), Some(0..8));
+        assert_eq!(write_range(3, 2, 8), Some(3..5));
+        assert_eq!(write_range(8, 0, 8), Some(8..8));
+        assert_eq!(write_range(7, 2, 8), None);
+        assert_eq!(write_range(9, 0, 8), None);
+        assert_eq!(write_range(u32::MAX, 2, usize::MAX), None);
+    }
+
+    #[test]
+    fn draw_ranges() {
+        let count = |first, count, total| {
+            let range = GpuDrawRange {
+                first,
+                count,
+                base_vertex: 0,
+            };
+            range.count_in(total).ok()
+        };
+        assert_eq!(count(0, 0, 6), Some(6));
+        assert_eq!(count(2, 0, 6), Some(4));
+        assert_eq!(count(2, 3, 6), Some(3));
+        assert_eq!(count(6, 0, 6), Some(0));
+        assert_eq!(count(4, 3, 6), None);
+        assert_eq!(count(7, 0, 6), None);
+        assert_eq!(count(u32::MAX, 2, 6), None);
+    }
+
+    #[test]
+    fn aligned_uploads() {
+        let data: Vec<u8> = (1..=10).collect();
+        let upload = |range| {
+            let (offset, data) = aligned_upload(&data, range);
+            (offset, data.into_owned())
+        };
+        assert_eq!(upload(4..8), (4, vec![5, 6, 7, 8]));
+        assert_eq!(upload(5..7), (4, vec![5, 6, 7, 8]));
+        // Past the end pads with zeros, like the gpu buffer.
+        assert_eq!(upload(6..10), (4, vec![5, 6, 7, 8, 9, 10, 0, 0]));
+        assert_eq!(upload(0..10).1.len(), 12);
+    }
+
+    #[test]
+    fn storage_limits() {
+        let mut system = System::default();
+        // Without a device, limits only grow to what's needed.
+        system.limits = Some(unsafe { std::mem::zeroed() });
+        for (len, read_only) in [(64, true), (256, false)] {
+            system.gpu.buffers.push(Arc::new(Mutex::new(GpuBuffer {
+                buffer: Default::default(),
+                data: vec![0; len],
+                detail: GpuBufferDetail::Storage {
+                    binding: 0,
+                    read_only,
+                },
+                size: 0,
+                dirty: None,
+            })));
+        }
+        assert!(check_limits(&mut system));
+        let limits = system.limits.unwrap();
+        assert_eq!(limits.maxBufferSize, 320);
+        assert_eq!(limits.maxStorageBufferBindingSize, 256);
+        assert_eq!(limits.maxStorageBuffersPerShaderStage, 2);
+        assert_eq!(limits.maxUniformBuffersPerShaderStage, 0);
+        assert_eq!(limits.maxInterStageShaderComponents, 6);
+    }
+
+    #[test]
+    fn texture_pitches() {
+        let pitch = |format, width, height| texture_pitch(format, width, height);
+        assert_eq!(pitch(native::WGPUTextureFormat_R8Unorm, 3, 5), Some((3, 5)));
+        assert_eq!(
+            pitch(native::WGPUTextureFormat_RG16Float, 3, 5),
+            Some((12, 5))
+        );
+        assert_eq!(
+            pitch(native::WGPUTextureFormat_RGBA32Float, 2, 1),
+            Some((32, 1))
+        );
+        assert_eq!(pitch(native::WGPUTextureFormat_Depth24Plus, 1, 1), None);
+        assert_eq!(pitch(native::WGPUTextureFormat_Undefined, 1, 1), None);
+        // Partial blocks still take whole blocks.
+        assert_eq!(
+            pitch(native::WGPUTextureFormat_BC1RGBAUnorm, 1, 1),
+            Some((8, 1))
+        );
+        // Such as the 9x8 level of a 36x32 texture.
+        assert_eq!(
+            pitch(native::WGPUTextureFormat_BC7RGBAUnorm, 9, 8),
+            Some((48, 2))
+        );
+        assert_eq!(
+            pitch(native::WGPUTextureFormat_EACRG11Unorm, 4, 4),
+            Some((16, 1))
+        );
+        assert_eq!(
+            texture_block(native::WGPUTextureFormat_ASTC10x6UnormSrgb),
+            Some((10, 6, 16))
+        );
+        assert_eq!(
+            texture_block(native::WGPUTextureFormat_ASTC12x12Unorm),
+            Some((12, 12, 16))
+        );
+    }
+
+    #[test]
+    fn texture_sizes() {
+        let check = |format, width, height| check_texture_size(format, width, height).is_ok();
+        assert!(check(native::WGPUTextureFormat_RGBA8Unorm, 3, 5));
+        assert!(!check(native::WGPUTextureFormat_RGBA8Unorm, 0, 5));
+        assert!(check(native::WGPUTextureFormat_BC7RGBAUnorm, 36, 32));
+        // Partial blocks only work in smaller mip levels.
+        assert!(!check(native::WGPUTextureFormat_BC7RGBAUnorm, 9, 8));
+        assert!(!check(native::WGPUTextureFormat_BC7RGBAUnorm, 8, 6));
+        assert!(check(native::WGPUTextureFormat_ASTC10x6Unorm, 20, 12));
+        assert!(!check(native::WGPUTextureFormat_ASTC10x6Unorm, 12, 12));
+    }
+
+    #[test]
+    fn texture_data_sizes() {
+        let size = |format, width, height| texture_data_size(format, width, height).ok();
+        assert_eq!(size(native::WGPUTextureFormat_RGBA8Unorm, 3, 5), Some(60));
+        assert_eq!(size(native::WGPUTextureFormat_BC7RGBAUnorm, 9, 8), Some(96));
+        // Exactly 2^32 bytes.
+        assert_eq!(
+            size(native::WGPUTextureFormat_RGBA32Float, 16384, 16384),
+            None
+        );
+        assert_eq!(
+            texture_pitch(native::WGPUTextureFormat_RGBA32Float, u32::MAX, 1),
+            None
+        );
+    }
+
+    #[test]
+    fn half_float_round_trip() {
+        for (half, value) in [
+            (0x0000, 0.0),
+            (0x8000, -0.0),
+            (0x3c00, 1.0),
+            (0xc000, -2.0),
+            (0x3555, 0.333_251_95),
+            (0x7bff, 65504.0),
+            (0x0400, 6.103_515_6e-5),
+            (0x0001, 5.960_464_5e-8),
+            (0x7c00, f32::INFINITY),
+        ] {
+            assert_eq!(f16_to_f32(half), value);
+            assert_eq!(f32_to_f16(value), half);
+        }
+        assert!(f16_to_f32(0x7e00).is_nan());
+        assert_eq!(f32_to_f16(f32::NAN) & 0x7fff, 0x7e00);
+        // Ties go to even.
+        assert_eq!(f32_to_f16(1.0 + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(f32_to_f16(1.0 + 3.0 * 2f32.powi(-11)), 0x3c02);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+    }
+
+    #[test]
+    fn downsample_half_floats() {
+        let data: Vec<u8> = [1.0, 2.0, 3.0, 6.0, 0.5, 0.5, -1.0, -1.0]
+            .into_iter()
+            .flat_map(|value| f32_to_f16(value).to_le_bytes())
+            .collect();
+        // 2x2 of two channels.
+        let next = downsample(native::WGPUTextureFormat_RG16Float, 2, 2, &data);
+        let next: Vec<f32> = next
+            .chunks(2)
+            .map(|half| f16_to_f32(u16::from_le_bytes(half.try_into().unwrap())))
+            .collect();
+        assert_eq!(next, [0.875, 1.875]);
+    }
+
+    #[test]
+    fn mip_level_counts() {
+        assert_eq!(mip_level_count(0, 1, 1).unwrap(), 1);
+        assert_eq!(mip_level_count(0, 256, 1).unwrap(), 9);
+        assert_eq!(mip_level_count(0, 5, 300).unwrap(), 9);
+        assert_eq!(mip_level_count(3, 8, 8).unwrap(), 3);
+        assert_eq!(mip_level_count(4, 8, 8).unwrap(), 4);
+        assert!(mip_level_count(5, 8, 8).is_err());
+        assert!(mip_level_count(2, 1, 1).is_err());
+    }
+
+    #[test]
+    fn render_target_formats() {
+        assert!(render_target_format_supported(
+            native::WGPUTextureFormat_RGBA8UnormSrgb
+        ));
+        assert!(render_target_format_supported(
+            native::WGPUTextureFormat_RGBA32Float
+        ));
+        assert!(render_target_format_supported(
+            native::WGPUTextureFormat_Depth32Float
+        ));
+        assert!(!render_target_format_supported(
+            native::WGPUTextureFormat_BC1RGBAUnorm
+        ));
+        assert!(!render_target_format_supported(
+            native::WGPUTextureFormat_ASTC4x4Unorm
+        ));
+        assert!(!render_target_format_supported(
+            native::WGPUTextureFormat_Undefined
+        ));
+    }
+
+    fn test_texture(
+        format: native::WGPUTextureFormat,
+        width: u32,
+        usage: native::WGPUTextureUsageFlags,
+    ) -> GpuTexture {
+        GpuTexture {
+            binding: 0,
+            descriptor: WGPUTextureDescriptor(native::WGPUTextureDescriptor {
+                nextInChain: null(),
+                label: null(),
+                usage,
+                dimension: native::WGPUTextureDimension_2D,
+                size: native::WGPUExtent3D {
+                    width,
+                    height: 4,
+                    depthOrArrayLayers: 1,
+                },
+                format,
+                mipLevelCount: 1,
+                sampleCount: 1,
+                viewFormatCount: 0,
+                viewFormats: null(),
+            }),
+            generate_mips: false,
+            levels: vec![],
+            storage: false,
+            texture: Default::default(),
+            texture_view: None,
+        }
+    }
+
+    #[test]
+    fn render_target_checks() {
+        let attachment = native::WGPUTextureUsage_RenderAttachment;
+        let textures = [
+            test_texture(native::WGPUTextureFormat_RGBA8Unorm, 4, attachment),
+            test_texture(native::WGPUTextureFormat_Depth32Float, 4, attachment),
+            test_texture(
+                native::WGPUTextureFormat_RGBA8Unorm,
+                4,
+                native::WGPUTextureUsage_TextureBinding,
+            ),
+            test_texture(native::WGPUTextureFormat_Depth24Plus, 8, attachment),
+        ];
+        let check = |color, depth| check_render_target(&textures, GpuTarget { color, depth });
+        assert!(check(0, 0).is_ok());
+        assert!(check(1, 0).is_ok());
+        assert!(check(0, 2).is_ok());
+        assert!(check(1, 2).is_ok());
+        // Out of range.
+        assert!(check(5, 0).is_err());
+        assert!(check(0, 5).is_err());
+        // Not made by renderTargetCreate.
+        assert!(check(3, 0).is_err());
+        // Wrong kind for the slot.
+        assert!(check(2, 0).is_err());
+        assert!(check(0, 1).is_err());
+        // Mismatched sizes.
+        assert!(check(1, 4).is_err());
+    }
+
+    #[test]
+    fn texture_regions() {
+        let size = native::WGPUExtent3D {
+            width: 10,
+            height: 6,
+            depthOrArrayLayers: 1,
+        };
+        let check = |block, x, y, width, height| {
+            check_texture_region(size, block, x, y, width, height).is_ok()
+        };
+        assert!(check((1, 1), 0, 0, 10, 6));
+        assert!(check((1, 1), 3, 5, 7, 1));
+        assert!(check((1, 1), 10, 6, 0, 0));
+        assert!(!check((1, 1), 3, 0, 8, 1));
+        assert!(!check((1, 1), 0, 1, 1, 6));
+        assert!(!check((1, 1), u32::MAX, 0, 2, 1));
+        assert!(!check((1, 1), 0, 1, 1, u32::MAX));
+        // Blocks partly past the right and bottom edges are fine.
+        assert!(check((4, 4), 4, 4, 6, 2));
+        assert!(check((4, 4), 0, 0, 8, 4));
+        assert!(!check((4, 4), 2, 0, 4, 4));
+        assert!(!check((4, 4), 0, 0, 6, 4));
+        assert!(!check((4, 4), 0, 0, 4, 2));
+    }
+
+    #[test]
+    fn texture_writes() {
+        use wasmer::{Function, FunctionEnv, Memory, MemoryType, Store};
+        let mut store = Store::default();
+        let env = FunctionEnv::new(&mut store, System::default());
+        let memory = Memory::new(&mut store, MemoryType::new(1, None, false)).unwrap();
+        let view = memory.view(&store);
+        // RGBA8Unorm info for 4x4, then one pixel of data.
+        view.write(0, &[0x12, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 4, 0, 0, 0])
+            .unwrap();
+        view.write(16, &[1, 2, 3, 4]).unwrap();
+        env.as_mut(&mut store).memory = Some(memory);
+        let create = Function::new_typed_with_env(&mut store, &env, taca_gpu_texture_create)
+            .typed::<(u32, u32), u32>(&store)
+            .unwrap();
+        let write = Function::new_typed_with_env(&mut store, &env, taca_gpu_texture_write)
+            .typed::<(u32, u32, u32, u32, u32, u32), ()>(&store)
+            .unwrap();
+        let texture = create.call(&mut store, 0, 0).unwrap();
+        // Empty regions never read their data.
+        write
+            .call(&mut store, texture, 1, 2, 0, 2, u32::MAX)
+            .unwrap();
+        write
+            .call(&mut store, texture, 4, 4, 0, 0, u32::MAX)
+            .unwrap();
+        assert!(env.as_ref(&store).gpu.textures[0].levels[0]
+            .iter()
+            .all(|&it| it == 0));
+        write.call(&mut store, texture, 1, 2, 1, 1, 16).unwrap();
+        let level = &env.as_ref(&store).gpu.textures[0].levels[0];
+        assert_eq!(level[36..40], [1, 2, 3, 4]);
+        assert_eq!(level.iter().filter(|&&it| it != 0).count(), 4);
+        assert!(write.call(&mut store, texture, 4, 0, 1, 1, 16).is_err());
+    }
 }
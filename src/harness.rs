@@ -0,0 +1,385 @@
+/// One line of a test script. Blank lines and `#` comments are skipped.
+#[derive(Debug, PartialEq)]
+enum Step {
+    /// `capture <name>` redraws and checks the frame against `<name>.png`.
+    Capture(String),
+    /// `frames <count>` redraws without checking.
+    Frames(u32),
+    /// `key <press|release> <KeyCode>`, with modifiers from held modifier keys
+    Key(KeyEvent),
+    /// `gamepad <pad> <connect|disconnect>`, `gamepad <pad> button <name>
+    /// <value>`, or `gamepad <pad> axis <name> <value>`
+    Gamepad(GamepadEvent),
+    /// `text <string>` types the rest of the line
+    Text(String),
+    /// `mouse <press|release> <Left|Right|Middle|index>`
+    MouseButton { button: u32, pressed: bool },
+    /// `mouse move <x> <y>` in physical pixels
+    MouseMove([f32; 2]),
+    /// `mouse wheel <x> <y>` in lines
+    MouseWheel([f32; 2]),
+}
+
+fn parse_script(text: &str) -> Result<Vec<Step>> {
+    let mut steps = vec![];
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let words: Vec<_> = line.split_whitespace().collect();
+        let step = match words.as_slice() {
+            ["capture", name] => Step::Capture(name.to_string()),
+            ["frames", count] => Step::Frames(count.parse()?),
+            ["key", state, name] => Step::Key(KeyEvent {
+                code: KeyCode::from_name(name).ok_or_else(|| anyhow!("Unknown key: {name}"))?,
+                // Filled in from held modifier keys when run.
+                modifiers: 0,
+                pressed: parse_pressed(state)?,
+                repeat: false,
+            }),
+            ["gamepad", pad, rest @ ..] => Step::Gamepad(parse_gamepad(pad, rest)?),
+            ["text", ..] => Step::Text(line["text".len()..].trim().to_string()),
+            ["mouse", "move", x, y] => Step::MouseMove([x.parse()?, y.parse()?]),
+            ["mouse", "wheel", x, y] => Step::MouseWheel([x.parse()?, y.parse()?]),
+            ["mouse", state, name] => Step::MouseButton {
+                button: MouseButton::from_name(name)
+                    .ok_or_else(|| anyhow!("Unknown mouse button: {name}"))?,
+                pressed: parse_pressed(state)?,
+            },
+            _ => bail!("Bad script line {}: {line}", index + 1),
+        };
+        steps.push(step);
+    }
+    Ok(steps)
+}
+
+fn parse_gamepad(pad: &str, words: &[&str]) -> Result<GamepadEvent> {
+    let (event_type, index, value) = match words {
+        ["connect"] => (GamepadEventType::Connect, 0, 0.0),
+        ["disconnect"] => (GamepadEventType::Disconnect, 0, 0.0),
+        ["button", name, value] => {
+            let button = GamepadButton::from_name(name)
+                .ok_or_else(|| anyhow!("Unknown gamepad button: {name}"))?;
+            (GamepadEventType::Button, button as u32, value.parse()?)
+        }
+        ["axis", name, value] => {
+            let axis = GamepadAxis::from_name(name)
+                .ok_or_else(|| anyhow!("Unknown gamepad axis: {name}"))?;
+            (GamepadEventType::Axis, axis as u32, value.parse()?)
+        }
+        _ => bail!("Bad gamepad step: {}", words.join(" ")),
+    };
+    let pad: u32 = pad.parse()?;
+    if pad >= MAX_GAMEPADS {
+        bail!("Gamepad index must be under {MAX_GAMEPADS}: {pad}");
+    }
+    Ok(GamepadEvent {
+        pad,
+        event_type,
+        index,
+        value,
+    })
+}
+
+fn parse_pressed(state: &str) -> Result<bool> {
+    match state {
+        "press" => Ok(true),
+        "release" => Ok(false),
+        _ => bail!("Expected press or release: {state}"),
+    }
+}
+
+pub fn run_test(args: &TestArgs) -> Result<()> {
+    let steps = parse_script(&fs::read_to_string(&args.script)?)?;
+    let system = System::headless(PhysicalSize::new(args.width, args.height));
+    let (mut store, env) = start_app(&args.app, &args.guest, system)?;
+    if env.as_ref(&store).window_listen.is_none() {
+        bail!("App never called taca_windowListen");
+    }
+    let mut captures = 0;
+    let mut failures = vec![];
+    let mut modifiers = 0;
+    for step in &steps {
+        match step {
+            Step::Capture(name) => {
+                send_event(&mut store, &env, WindowEventType::Redraw)?;
+                let actual = read_offscreen_frame(env.as_ref(&store))?;
+                captures += 1;
+                if !check_capture(args, name, &actual)? {
+                    failures.push(name.as_str());
+                }
+            }
+            Step::Frames(count) => {
+                for _ in 0..*count {
+                    send_event(&mut store, &env, WindowEventType::Redraw)?;
+                }
+            }
+            Step::Key(key_event) => {
+                match key_event.pressed {
+                    true => modifiers |= key_event.code.modifier(),
+                    false => modifiers &= !key_event.code.modifier(),
+                }
+                env.as_mut(&mut store).key_event = Some(KeyEvent {
+                    modifiers,
+                    ..*key_event
+                });
+                send_event(&mut store, &env, WindowEventType::Key)?;
+            }
+            Step::Gamepad(event) => send_gamepad_event(&mut store, &env, *event)?,
+            Step::Text(text) => {
+                env.as_mut(&mut store).text_event = TextEvent {
+                    text: text.clone(),
+                    ..Default::default()
+                };
+                send_event(&mut store, &env, WindowEventType::Text)?;
+            }
+            Step::MouseButton { button, pressed } => {
+                let mouse_event = &mut env.as_mut(&mut store).mouse_event;
+                mouse_event.button = *button;
+                mouse_event.pressed = *pressed;
+                send_event(&mut store, &env, WindowEventType::MouseButton)?;
+            }
+            Step::MouseMove(position) => {
+                env.as_mut(&mut store).mouse_event.position = *position;
+                send_event(&mut store, &env, WindowEventType::MouseMove)?;
+            }
+            Step::MouseWheel(delta) => {
+                let mouse_event = &mut env.as_mut(&mut store).mouse_event;
+                mouse_event.delta = *delta;
+                mouse_event.delta_pixels = false;
+                send_event(&mut store, &env, WindowEventType::MouseWheel)?;
+            }
+        }
+    }
+    send_event(&mut store, &env, WindowEventType::Close)?;
+    if !failures.is_empty() {
+        bail!(
+            "{} of {captures} captures failed: {}",
+            failures.len(),
+            failures.join(", ")
+        );
+    }
+    println!("{captures} captures passed");
+    Ok(())
+}
+
+fn check_capture(args: &TestArgs, name: &str, actual: &Frame) -> Result<bool> {
+    let reference_path = args.reference.join(format!("{name}.png"));
+    if args.update {
+        fs::create_dir_all(&args.reference)?;
+        write_png(&reference_path, actual)?;
+        println!("{name}: updated {}", reference_path.display());
+        return Ok(true);
+    }
+    let mut diff = None;
+    let problem = if !reference_path.exists() {
+        format!("missing {}", reference_path.display())
+    } else {
+        let expected = read_png(&reference_path)?;
+        if (expected.width, expected.height) != (actual.width, actual.height) {
+            format!(
+                "size {}x{} but expected {}x{}",
+                actual.width, actual.height, expected.width, expected.height
+            )
+        } else {
+            let (count, frame) = diff_frames(actual, &expected, args.tolerance);
+            if count == 0 {
+                return Ok(true);
+            }
+            diff = Some(frame);
+            format!("{count} pixels differ")
+        }
+    };
+    fs::create_dir_all(&args.output)?;
+    write_png(&args.output.join(format!("{name}.png")), actual)?;
+    if let Some(diff) = diff {
+        write_png(&args.output.join(format!("{name}-diff.png")), &diff)?;
+    }
+    println!("{name}: {problem}");
+    Ok(false)
+}
+
+/// Counts pixels with any channel off by more than the tolerance, and marks
+/// them red over a faded gray copy of the actual frame.
+fn diff_frames(actual: &Frame, expected: &Frame, tolerance: u8) -> (usize, Frame) {
+    let mut count = 0;
+    let mut data = Vec::with_capacity(actual.data.len());
+    for (a, e) in actual.data.chunks(4).zip(expected.data.chunks(4)) {
+        let differs = a.iter().zip(e).any(|(a, e)| a.abs_diff(*e) > tolerance);
+        if differs {
+            count += 1;
+            data.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let gray = ((a[0] as u32 + a[1] as u32 + a[2] as u32) / 12) as u8;
+            data.extend_from_slice(&[gray, gray, gray, 255]);
+        }
+    }
+    let frame = Frame {
+        width: actual.width,
+        height: actual.height,
+        data,
+    };
+    (count, frame)
+}
+
+use crate::{
+    capture::{read_offscreen_frame, read_png, write_png, Frame},
+    gamepad::{
+        send_gamepad_event, GamepadAxis, GamepadButton, GamepadEvent, GamepadEventType,
+        MAX_GAMEPADS,
+    },
+    start_app,
+    system::System,
+    window::{send_event, KeyCode, KeyEvent, MouseButton, TextEvent, WindowEventType},
+    TestArgs,
+};
+use anyhow::{anyhow, bail, Result};
+use std::fs;
+use winit::dpi::PhysicalSize;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(pixels: &[[u8; 4]]) -> Frame {
+        Frame {
+            width: pixels.len() as u32,
+            height: 1,
+            data: pixels.concat(),
+        }
+    }
+
+    #[test]
+    fn parse_script_steps() {
+        let steps = parse_script(
+            "
+            # Comment
+            frames 3
+            key press Left # Trailing comment
+            capture done
+            ",
+        )
+        .unwrap();
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[0], Step::Frames(3));
+        assert!(matches!(
+            steps[1],
+            Step::Key(KeyEvent {
+                code: KeyCode::Left,
+                pressed: true,
+                ..
+            })
+        ));
+        assert_eq!(steps[2], Step::Capture("done".into()));
+    }
+
+    #[test]
+    fn parse_script_errors() {
+        let message = |text| parse_script(text).err().unwrap().to_string();
+        assert_eq!(message("frames 1\njump"), "Bad script line 2: jump");
+        assert_eq!(message("key press Nope"), "Unknown key: Nope");
+        assert_eq!(message("key hold Left"), "Expected press or release: hold");
+        assert!(parse_script("frames many").is_err());
+    }
+
+    #[test]
+    fn parse_mouse_steps() {
+        let steps = parse_script(
+            "
+            mouse move 1.5 2
+            mouse wheel 0 -1
+            mouse press Left
+            mouse release 2
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            steps,
+            [
+                Step::MouseMove([1.5, 2.0]),
+                Step::MouseWheel([0.0, -1.0]),
+                Step::MouseButton {
+                    button: MouseButton::Left as u32,
+                    pressed: true,
+                },
+                Step::MouseButton {
+                    button: MouseButton::Other as u32 + 2,
+                    pressed: false,
+                },
+            ]
+        );
+        let message = |text| parse_script(text).err().unwrap().to_string();
+        assert_eq!(message("mouse press Side"), "Unknown mouse button: Side");
+        assert!(parse_script("mouse move 1").is_err());
+    }
+
+    #[test]
+    fn parse_text_steps() {
+        let steps = parse_script("text hello  there # Comment\ntext").unwrap();
+        assert_eq!(
+            steps,
+            [Step::Text("hello  there".into()), Step::Text("".into())]
+        );
+    }
+
+    #[test]
+    fn parse_gamepad_steps() {
+        let event = |pad, words: &str| {
+            let words: Vec<_> = words.split_whitespace().collect();
+            parse_gamepad(pad, &words).unwrap()
+        };
+        assert_eq!(
+            event("1", "connect"),
+            GamepadEvent {
+                pad: 1,
+                event_type: GamepadEventType::Connect,
+                index: 0,
+                value: 0.0,
+            }
+        );
+        assert_eq!(
+            event("0", "button RightTrigger 0.5"),
+            GamepadEvent {
+                pad: 0,
+                event_type: GamepadEventType::Button,
+                index: GamepadButton::RightTrigger as u32,
+                value: 0.5,
+            }
+        );
+        assert_eq!(
+            event("2", "axis LeftY -1"),
+            GamepadEvent {
+                pad: 2,
+                event_type: GamepadEventType::Axis,
+                index: GamepadAxis::LeftY as u32,
+                value: -1.0,
+            }
+        );
+        assert!(parse_gamepad("0", &["button", "Z", "1"]).is_err());
+        assert!(parse_gamepad("0", &["axis", "LeftZ", "1"]).is_err());
+        assert!(parse_gamepad("x", &["connect"]).is_err());
+        assert!(parse_gamepad("0", &["rumble"]).is_err());
+        assert!(parse_gamepad("4000000000", &["connect"]).is_err());
+        assert!(parse_script("gamepad 16 connect").is_err());
+    }
+
+    #[test]
+    fn diff_frames_tolerance() {
+        let expected = frame(&[[10, 20, 30, 255], [0, 0, 0, 255]]);
+        let actual = frame(&[[12, 20, 30, 255], [0, 0, 0, 255]]);
+        assert_eq!(diff_frames(&actual, &expected, 2).0, 0);
+        let (count, diff) = diff_frames(&actual, &expected, 1);
+        assert_eq!(count, 1);
+        assert_eq!((diff.width, diff.height), (2, 1));
+        assert_eq!(diff.data, [255, 0, 0, 255, 0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn diff_frames_alpha() {
+        let expected = frame(&[[0, 0, 0, 255]]);
+        let actual = frame(&[[0, 0, 0, 0]]);
+        assert_eq!(diff_frames(&actual, &expected, 254).0, 1);
+        assert_eq!(diff_frames(&actual, &expected, 255).0, 0);
+    }
+}
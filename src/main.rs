@@ -13,11 +13,107 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     Run(RunArgs),
+    Test(TestArgs),
 }
 
 #[derive(Args)]
 struct RunArgs {
     app: String,
+    /// Render offscreen with a fallback adapter instead of opening a window
+    #[arg(long)]
+    headless: bool,
+    /// Number of frames to render when headless
+    #[arg(long, default_value_t = 1)]
+    frames: u32,
+    /// Write numbered png files of each frame to this dir, implying headless
+    #[arg(long)]
+    capture_frames: Option<PathBuf>,
+    /// Offscreen width when headless
+    #[arg(long, default_value_t = 800)]
+    width: u32,
+    /// Offscreen height when headless
+    #[arg(long, default_value_t = 600)]
+    height: u32,
+    #[command(flatten)]
+    guest: GuestArgs,
+}
+
+/// What the app sees through wasi.
+#[derive(Args)]
+struct GuestArgs {
+    /// Dir the app can read and write, repeatable
+    #[arg(long = "dir", value_name = "HOST[:GUEST]", value_parser = parse_dir)]
+    dirs: Vec<DirMapping>,
+    /// Dir the app can only read, repeatable
+    #[arg(long = "dir-ro", value_name = "HOST[:GUEST]", value_parser = parse_dir)]
+    dirs_ro: Vec<DirMapping>,
+    /// Environment variable for the app, repeatable
+    #[arg(long = "env", value_name = "KEY=VAL", value_parser = parse_env)]
+    envs: Vec<(String, String)>,
+    /// Arguments for the app, after --
+    #[arg(last = true)]
+    args: Vec<String>,
+}
+
+/// Host dir exposed to the app, at the same path unless given a guest path.
+#[derive(Clone)]
+struct DirMapping {
+    host: PathBuf,
+    guest: String,
+}
+
+fn parse_dir(text: &str) -> std::result::Result<DirMapping, String> {
+    // Guest paths come last, since host paths can have drive letters.
+    let (host, guest) = match text.rsplit_once(':') {
+        Some((drive, _)) if cfg!(windows) && is_drive(drive) => (text, text),
+        Some(split) => split,
+        None => (text, text),
+    };
+    if host.is_empty() || guest.is_empty() {
+        return Err(format!("expected HOST[:GUEST]: {text}"));
+    }
+    Ok(DirMapping {
+        host: host.into(),
+        guest: guest.to_string(),
+    })
+}
+
+fn is_drive(text: &str) -> bool {
+    text.len() == 1 && text.as_bytes()[0].is_ascii_alphabetic()
+}
+
+fn parse_env(text: &str) -> std::result::Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((key, value)) => Ok((key.to_string(), value.to_string())),
+        None => Err(format!("expected KEY=VAL: {text}")),
+    }
+}
+
+#[derive(Args)]
+struct TestArgs {
+    app: String,
+    /// Script of frames, input events, and named captures to check
+    script: PathBuf,
+    /// Dir of reference png files named after script captures
+    #[arg(long)]
+    reference: PathBuf,
+    /// Dir for actual and diff png files of failed captures
+    #[arg(long, default_value = "test-output")]
+    output: PathBuf,
+    /// Max allowed difference per color channel
+    #[arg(long, default_value_t = 0)]
+    tolerance: u8,
+    /// Write captures as the new references instead of comparing
+    #[arg(long)]
+    update: bool,
+    /// Offscreen width
+    #[arg(long, default_value_t = 800)]
+    width: u32,
+    /// Offscreen height
+    #[arg(long, default_value_t = 600)]
+    height: u32,
+    #[command(flatten)]
+    guest: GuestArgs,
 }
 
 async fn run() -> Result<()> {
@@ -28,6 +124,9 @@ async fn run() -> Result<()> {
         Commands::Run(args) => {
             run_app(args)?;
         }
+        Commands::Test(args) => {
+            run_test(args)?;
+        }
     }
 
     // let state = State::new(window).await;
@@ -40,42 +139,128 @@ async fn run() -> Result<()> {
 }
 
 fn run_app(args: &RunArgs) -> Result<()> {
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new()
-        .with_maximized(true)
-        .build(&event_loop)
-        .unwrap();
-    window.set_title("Taca");
+    let headless = args.headless || args.capture_frames.is_some();
+    let (event_loop, mut system) = match headless {
+        true => (
+            None,
+            System::headless(PhysicalSize::new(args.width, args.height)),
+        ),
+        false => {
+            let event_loop = EventLoop::new();
+            let window = WindowBuilder::new()
+                .with_maximized(true)
+                .build(&event_loop)
+                .unwrap();
+            window.set_title("Taca");
+            window.set_ime_allowed(true);
+            (Some(event_loop), System::new(window))
+        }
+    };
+
+    if let Some(dir) = &args.capture_frames {
+        fs::create_dir_all(dir)?;
+        system.capture = Some(FrameCapture::new(dir.clone()));
+    }
+
+    let (store, env) = start_app(&args.app, &args.guest, system)?;
+    if env.as_ref(&store).window_listen.is_some() {
+        match event_loop {
+            Some(event_loop) => run_loop(event_loop, store, env, gilrs_source()),
+            // Keep real devices out of headless runs, which should repeat.
+            None => run_headless(store, env, args.frames)?,
+        }
+    }
+
+    Ok(())
+}
 
+fn start_app(app: &str, guest: &GuestArgs, system: System) -> Result<(Store, FunctionEnv<System>)> {
     let mut store = Store::default();
-    let module = Module::from_file(&store, args.app.as_str())?;
-    let env = FunctionEnv::new(&mut store, System::new(window));
-    let import_object = imports! {
+    let module = Module::from_file(&store, app)?;
+    let env = FunctionEnv::new(&mut store, system);
+    let mut wasi_builder = WasiEnv::builder(app)
+        .args(&guest.args)
+        .envs(guest.envs.clone());
+    let dirs = (guest.dirs.iter().map(|dir| (dir, true)))
+        .chain(guest.dirs_ro.iter().map(|dir| (dir, false)))
+        .collect::<Vec<_>>();
+    if !dirs.is_empty() {
+        // Without preopens, the app sees no files at all.
+        wasi_builder = wasi_builder.fs(Box::new(host_fs::FileSystem::default()));
+    }
+    for (dir, writable) in dirs {
+        wasi_builder = wasi_builder.preopen_build(|preopen| {
+            preopen
+                .directory(&dir.host)
+                .alias(&dir.guest)
+                .read(true)
+                .write(writable)
+                .create(writable)
+        })?;
+    }
+    let mut wasi_env = wasi_builder.finalize(&mut store)?;
+    let mut import_object = imports! {
         "env" => {
+            "taca_gpu_blendModeUse" => Function::new_typed_with_env(&mut store, &env, taca_gpu_blend_mode_use),
+            "taca_gpu_bufferResize" => Function::new_typed_with_env(&mut store, &env, taca_gpu_buffer_resize),
             "taca_gpu_bufferWrite" => Function::new_typed_with_env(&mut store, &env, taca_gpu_buffer_write),
+            "taca_gpu_bufferWriteRange" => Function::new_typed_with_env(&mut store, &env, taca_gpu_buffer_write_range),
+            "taca_gpu_clear" => Function::new_typed_with_env(&mut store, &env, taca_gpu_clear),
+            "taca_gpu_computeCreate" => Function::new_typed_with_env(&mut store, &env, taca_gpu_compute_create),
+            "taca_gpu_computeUse" => Function::new_typed_with_env(&mut store, &env, taca_gpu_compute_use),
+            "taca_gpu_dispatch" => Function::new_typed_with_env(&mut store, &env, taca_gpu_dispatch),
             "taca_gpu_draw" => Function::new_typed_with_env(&mut store, &env, taca_gpu_draw),
+            "taca_gpu_drawInstanced" => Function::new_typed_with_env(&mut store, &env, taca_gpu_draw_instanced),
+            "taca_gpu_drawRange" => Function::new_typed_with_env(&mut store, &env, taca_gpu_draw_range),
+            "taca_gpu_frameOptions" => Function::new_typed_with_env(&mut store, &env, taca_gpu_frame_options),
             "taca_gpu_indexBufferCreate" => Function::new_typed_with_env(&mut store, &env, taca_gpu_index_buffer_create),
+            "taca_gpu_pipelineCreate" => Function::new_typed_with_env(&mut store, &env, taca_gpu_pipeline_create),
+            "taca_gpu_pipelineUse" => Function::new_typed_with_env(&mut store, &env, taca_gpu_pipeline_use),
             "taca_gpu_present" => Function::new_typed_with_env(&mut store, &env, taca_gpu_present),
+            "taca_gpu_renderTargetCreate" => Function::new_typed_with_env(&mut store, &env, taca_gpu_render_target_create),
+            "taca_gpu_renderTargetUse" => Function::new_typed_with_env(&mut store, &env, taca_gpu_render_target_use),
+            "taca_gpu_samplerCreate" => Function::new_typed_with_env(&mut store, &env, taca_gpu_sampler_create),
             "taca_gpu_shaderCreate" => Function::new_typed_with_env(&mut store, &env, taca_gpu_shader_create),
             "taca_gpu_uniformBufferCreate" => Function::new_typed_with_env(&mut store, &env, taca_gpu_uniform_buffer_create),
+            "taca_gpu_storageBufferCreate" => Function::new_typed_with_env(&mut store, &env, taca_gpu_storage_buffer_create),
+            "taca_gpu_storageTextureCreate" => Function::new_typed_with_env(&mut store, &env, taca_gpu_storage_texture_create),
             "taca_gpu_textureCreate" => Function::new_typed_with_env(&mut store, &env, taca_gpu_texture_create),
+            "taca_gpu_textureCreateEx" => Function::new_typed_with_env(&mut store, &env, taca_gpu_texture_create_ex),
+            "taca_gpu_textureFormatSupported" => Function::new_typed_with_env(&mut store, &env, taca_gpu_texture_format_supported),
+            "taca_gpu_textureWrite" => Function::new_typed_with_env(&mut store, &env, taca_gpu_texture_write),
+            "taca_gpu_textureWriteMip" => Function::new_typed_with_env(&mut store, &env, taca_gpu_texture_write_mip),
             "taca_gpu_vertexBufferCreate" => Function::new_typed_with_env(&mut store, &env, taca_gpu_vertex_buffer_create),
+            "taca_gamepadAxis" => Function::new_typed_with_env(&mut store, &env, taca_gamepad_axis),
+            "taca_gamepadButton" => Function::new_typed_with_env(&mut store, &env, taca_gamepad_button),
+            "taca_gamepadConnected" => Function::new_typed_with_env(&mut store, &env, taca_gamepad_connected),
+            "taca_gamepadCount" => Function::new_typed_with_env(&mut store, &env, taca_gamepad_count),
+            "taca_gamepadEvent" => Function::new_typed_with_env(&mut store, &env, taca_gamepad_event),
             "taca_keyEvent" => Function::new_typed_with_env(&mut store, &env, taca_key_event),
+            "taca_mouseEvent" => Function::new_typed_with_env(&mut store, &env, taca_mouse_event),
+            "taca_textEvent" => Function::new_typed_with_env(&mut store, &env, taca_text_event),
             "taca_windowInnerSize" => Function::new_typed_with_env(&mut store, &env, taca_window_inner_size),
             "taca_windowListen" => Function::new_typed_with_env(&mut store, &env, taca_window_listen),
             "taca_windowSetTitle" => Function::new_typed_with_env(&mut store, &env, taca_window_set_title),
             "wgpuAdapterDrop" => Function::new_typed_with_env(&mut store, &env, wgpu_adapter_drop),
             "wgpuAdapterGetLimits" => Function::new_typed_with_env(&mut store, &env, wgpu_adapter_get_limits),
+            "wgpuAdapterHasFeature" => Function::new_typed_with_env(&mut store, &env, wgpu_adapter_has_feature),
             "wgpuAdapterRequestDevice" => Function::new_typed_with_env(&mut store, &env, wgpu_adapter_request_device),
+            "wgpuCommandEncoderBeginComputePass" => Function::new_typed_with_env(&mut store, &env, wgpu_command_encoder_begin_compute_pass),
             "wgpuCommandEncoderBeginRenderPass" => Function::new_typed_with_env(&mut store, &env, wgpu_command_encoder_begin_render_pass),
             "wgpuCommandEncoderFinish" => Function::new_typed_with_env(&mut store, &env, wgpu_command_encoder_finish),
+            "wgpuComputePassEncoderDispatchWorkgroups" => Function::new_typed_with_env(&mut store, &env, wgpu_compute_pass_encoder_dispatch_workgroups),
+            "wgpuComputePassEncoderEnd" => Function::new_typed_with_env(&mut store, &env, wgpu_compute_pass_encoder_end),
+            "wgpuComputePassEncoderSetBindGroup" => Function::new_typed_with_env(&mut store, &env, wgpu_compute_pass_encoder_set_bind_group),
+            "wgpuComputePassEncoderSetPipeline" => Function::new_typed_with_env(&mut store, &env, wgpu_compute_pass_encoder_set_pipeline),
             "wgpuCreateInstance" => Function::new_typed_with_env(&mut store, &env, wgpu_create_instance),
             "wgpuDeviceCreateBindGroup" => Function::new_typed_with_env(&mut store, &env, wgpu_device_create_bind_group),
             "wgpuDeviceCreateBindGroupLayout" => Function::new_typed_with_env(&mut store, &env, wgpu_device_create_bind_group_layout),
             "wgpuDeviceCreateBuffer" => Function::new_typed_with_env(&mut store, &env, wgpu_device_create_buffer),
             "wgpuDeviceCreateCommandEncoder" => Function::new_typed_with_env(&mut store, &env, wgpu_device_create_command_encoder),
+            "wgpuDeviceCreateComputePipeline" => Function::new_typed_with_env(&mut store, &env, wgpu_device_create_compute_pipeline),
             "wgpuDeviceCreatePipelineLayout" => Function::new_typed_with_env(&mut store, &env, wgpu_device_create_pipeline_layout),
             "wgpuDeviceCreateRenderPipeline" => Function::new_typed_with_env(&mut store, &env, wgpu_device_create_render_pipeline),
+            "wgpuDeviceCreateSampler" => Function::new_typed_with_env(&mut store, &env, wgpu_device_create_sampler),
             "wgpuDeviceCreateShaderModule" => Function::new_typed_with_env(&mut store, &env, wgpu_device_create_shader_module),
             "wgpuDeviceCreateSwapChain" => Function::new_typed_with_env(&mut store, &env, wgpu_device_create_swap_chain),
             "wgpuDeviceCreateTexture" => Function::new_typed_with_env(&mut store, &env, wgpu_device_create_texture),
@@ -104,23 +289,12 @@ fn run_app(args: &RunArgs) -> Result<()> {
             "wgpuTextureDestroy" => Function::new_typed_with_env(&mut store, &env, wgpu_texture_destroy),
             "wgpuTextureViewDrop" => Function::new_typed_with_env(&mut store, &env, wgpu_texture_view_drop),
         },
-        // TODO Combine ours with wasmer_wasix::WasiEnv???
-        "wasi_snapshot_preview1" => {
-            "args_get" => Function::new_typed_with_env(&mut store, &env, wasi_args_get),
-            "args_sizes_get" => Function::new_typed_with_env(&mut store, &env, wasi_args_sizes_get),
-            "fd_close" => Function::new_typed_with_env(&mut store, &env, wasi_fd_close),
-            "fd_fdstat_get" => Function::new_typed_with_env(&mut store, &env, wasi_fd_fdstat_get),
-            "fd_seek" => Function::new_typed_with_env(&mut store, &env, wasi_fd_seek),
-            "fd_write" => Function::new_typed_with_env(&mut store, &env, wasi_fd_write),
-            "proc_exit" => Function::new_typed(&mut store, wasi_proc_exit),
-        },
     };
+    import_object.extend(&wasi_env.import_object(&mut store, &module)?);
     let instance = Instance::new(&mut store, &module, &import_object)?;
+    wasi_env.initialize(&mut store, instance.clone())?;
     let env_mut = env.as_mut(&mut store);
     env_mut.memory = Some(instance.exports.get_memory("memory")?.clone());
-    // See for memory access: https://github.com/wasmerio/wasmer/blob/ef5dbd498722d1852ef05774f2c50f886c32ba80/examples/wasi_manual_setup.rs
-    // Something like this: wasi_env.data_mut(&mut store).set_memory(memory.clone());
-    // TODO Check function type to see if cli args are expected?
     let _start = instance.exports.get_function("_start")?;
     env_mut.functions = instance
         .exports
@@ -137,12 +311,15 @@ fn run_app(args: &RunArgs) -> Result<()> {
         Ok(_) => {
             // println!("Non error termination");
         }
-        Err(err) => match err.downcast::<ExitCode>() {
-            Ok(exit_code) => {
-                if exit_code.0 != 0 {
+        Err(err) => match err.downcast::<WasiError>() {
+            Ok(WasiError::Exit(exit_code)) => {
+                if !exit_code.is_success() {
                     bail!("Exit code: {exit_code}");
                 }
             }
+            Ok(err) => {
+                bail!("Unexpected error {err}");
+            }
             Err(err) => {
                 bail!("Unexpected error {err}");
             }
@@ -152,152 +329,60 @@ fn run_app(args: &RunArgs) -> Result<()> {
     // let result = add_one.call(&mut store, &[Value::I32(42)])?;
     // println!("After: {}", result[0].unwrap_i32());
 
-    if env.as_ref(&store).window_listen.is_some() {
-        run_loop(event_loop, store, env);
-    }
-
-    Ok(())
-}
-
-#[derive(Copy, Clone, Debug, ValueType)]
-#[repr(C)]
-struct WasmIOVec {
-    buf: WasmPtr<u8>,
-    size: u32,
-}
-
-fn wasi_args_get(_env: FunctionEnvMut<System>, _argv: u32, _argv_buf: u32) -> u32 {
-    0
-}
-
-fn wasi_args_sizes_get(mut env: FunctionEnvMut<System>, argv_size: u32, argv_buf_size: u32) -> u32 {
-    let (system, store) = env.data_and_store_mut();
-    let view = system.memory.as_ref().unwrap().view(&store);
-    view.write(argv_size as u64, &[0]).unwrap();
-    view.write(argv_buf_size as u64, &[0]).unwrap();
-    0
-}
-
-fn wasi_fd_close(_env: FunctionEnvMut<System>, _fd: u32) -> u32 {
-    0
-}
-
-#[derive(Copy, Clone, Debug, ValueType)]
-#[repr(C)]
-struct WasiFdStat {
-    file_type: u8,
-    _fill1: u8,
-    flags: u16,
-    _fill2: u32,
-    rights_base: u64,
-    rights_inheriting: u64,
-}
-
-#[allow(dead_code)]
-#[derive(Copy, Clone, Debug)]
-#[repr(u8)]
-enum WasiFileType {
-    Unknown = 0,
-    BlockDevice,
-    CharacterDevice,
-    Directory,
-    RegularFile,
-    SocketDgram,
-    SocketStream,
-    SymbolicLink,
-}
-
-fn wasi_fd_fdstat_get(mut env: FunctionEnvMut<System>, _fd: u32, fdstat: u32) -> u32 {
-    let (system, store) = env.data_and_store_mut();
-    let view = system.memory.as_ref().unwrap().view(&store);
-    let fdstat = WasmRef::<WasiFdStat>::new(&view, fdstat as u64);
-    fdstat
-        .write(WasiFdStat {
-            file_type: WasiFileType::CharacterDevice as u8,
-            _fill1: 0,
-            flags: 0,
-            _fill2: 0,
-            rights_base: 0,
-            rights_inheriting: 0,
-        })
-        .unwrap();
-    0
-}
-
-fn wasi_fd_seek(
-    mut env: FunctionEnvMut<System>,
-    _fd: u32,
-    _filedelta: u64,
-    _whence: u32,
-    new_offset: u32,
-) -> u32 {
-    let (system, store) = env.data_and_store_mut();
-    let view = system.memory.as_ref().unwrap().view(&store);
-    view.write(new_offset as u64, &[0]).unwrap();
-    1
-}
-
-fn wasi_fd_write(
-    mut env: FunctionEnvMut<System>,
-    fd: u32,
-    iovec: u32,
-    len: u32,
-    nwritten: u32,
-) -> u32 {
-    let (system, store) = env.data_and_store_mut();
-    let view = system.memory.as_ref().unwrap().view(&store);
-    let mut count = 0u32;
-    for io in WasmPtr::<WasmIOVec>::new(iovec)
-        .slice(&view, len)
-        .unwrap()
-        .iter()
-    {
-        let io = io.read().unwrap();
-        // TODO Support arbitrary bytes to output streams? Depends on config???
-        let text = io.buf.read_utf8_string(&view, io.size).unwrap();
-        match fd {
-            1 => print!("{}", text),
-            _ => eprint!("{}", text),
-        }
-        count += io.size;
-    }
-    WasmRef::<u32>::new(&view, nwritten as u64)
-        .write(count)
-        .unwrap();
-    0
-}
-
-#[derive(Debug, Clone, Copy)]
-struct ExitCode(u32);
-
-impl fmt::Display for ExitCode {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
-    }
-}
-
-impl std::error::Error for ExitCode {}
-
-fn wasi_proc_exit(code: u32) -> std::result::Result<(), ExitCode> {
-    println!("proc_exit({code})");
-    Err(ExitCode(code))
+    Ok((store, env))
 }
 
+mod capture;
+mod gamepad;
 mod gpu;
+mod harness;
 mod system;
 mod webgpu;
 mod window;
 
 use anyhow::{bail, Result};
 use clap::{Args, Parser, Subcommand};
-use std::fmt;
-use wasmer::{
-    imports, Function, FunctionEnv, FunctionEnvMut, Instance, Module, Store, ValueType, WasmPtr,
-    WasmRef,
-};
-use winit::{event_loop::EventLoop, window::WindowBuilder};
+use std::{fs, path::PathBuf};
+use wasmer::{imports, Function, FunctionEnv, Instance, Module, Store};
+use wasmer_wasix::{virtual_fs::host_fs, WasiEnv, WasiError};
+use winit::{dpi::PhysicalSize, event_loop::EventLoop, window::WindowBuilder};
 
+use crate::capture::*;
+use crate::gamepad::*;
 use crate::gpu::*;
+use crate::harness::*;
 use crate::system::*;
 use crate::webgpu::*;
 use crate::window::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(text: &str) -> (PathBuf, String) {
+        let dir = parse_dir(text).unwrap();
+        (dir.host, dir.guest)
+    }
+
+    #[test]
+    fn parse_dir_mappings() {
+        assert_eq!(dir("assets"), ("assets".into(), "assets".into()));
+        assert_eq!(dir("assets:/assets"), ("assets".into(), "/assets".into()));
+        assert_eq!(dir("a:b:/c"), ("a:b".into(), "/c".into()));
+        assert!(parse_dir(":/assets").is_err());
+        assert!(parse_dir("assets:").is_err());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn parse_dir_drives() {
+        assert_eq!(
+            dir(r"C:\levels"),
+            (r"C:\levels".into(), r"C:\levels".into())
+        );
+        assert_eq!(
+            dir(r"C:\levels:/levels"),
+            (r"C:\levels".into(), "/levels".into())
+        );
+    }
+}
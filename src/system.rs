@@ -1,4 +1,5 @@
 pub type WGPUAdapter = Pointer<native::WGPUAdapterImpl>;
+pub type WGPUComputePassEncoder = Pointer<native::WGPUComputePassEncoderImpl>;
 pub type WGPUInstance = Pointer<native::WGPUInstanceImpl>;
 pub type WGPURenderPassEncoder = Pointer<native::WGPURenderPassEncoderImpl>;
 pub type WGPUSwapChain = Pointer<native::WGPUSwapChainImpl>;
@@ -67,6 +68,14 @@ impl Default for WGPUQueue {
     }
 }
 
+pub struct WGPUComputePipeline(pub native::WGPUComputePipeline);
+unsafe impl Send for WGPUComputePipeline {}
+impl Default for WGPUComputePipeline {
+    fn default() -> Self {
+        WGPUComputePipeline(null_mut())
+    }
+}
+
 pub struct WGPUPipelineLayout(pub native::WGPUPipelineLayout);
 unsafe impl Send for WGPUPipelineLayout {}
 impl Default for WGPUPipelineLayout {
@@ -83,6 +92,14 @@ impl Default for WGPURenderPipeline {
     }
 }
 
+pub struct WGPUSampler(pub native::WGPUSampler);
+unsafe impl Send for WGPUSampler {}
+impl Default for WGPUSampler {
+    fn default() -> Self {
+        WGPUSampler(null_mut())
+    }
+}
+
 pub struct WGPUShaderModule(pub native::WGPUShaderModule);
 unsafe impl Send for WGPUShaderModule {}
 impl Default for WGPUShaderModule {
@@ -121,25 +138,33 @@ pub struct System {
     pub bind_groups: Vec<WGPUBindGroup>,
     pub bind_group_layouts: Vec<WGPUBindGroupLayout>,
     pub buffers: Vec<WGPUBuffer>,
+    pub capture: Option<FrameCapture>,
     pub command_buffer: WGPUCommandBuffer,
+    pub compute_pass: WGPUComputePassEncoder,
+    pub compute_pipelines: Vec<WGPUComputePipeline>,
     pub device: WGPUDevice,
     pub device_uncaptured_error_callback: Option<wasmer::Function>,
     pub device_uncaptured_error_callback_userdata: u32,
     pub encoder: WGPUCommandEncoder,
     pub functions: Option<Table>,
+    pub gamepads: Gamepads,
     pub gpu: SimpleGpu,
     pub instance: WGPUInstance,
     pub key_event: Option<crate::window::KeyEvent>,
     pub limits: Option<native::WGPULimits>,
     pub memory: Option<Memory>,
+    pub mouse_event: crate::window::MouseEvent,
     pub named_window_listen: Option<wasmer::Function>,
+    pub offscreen: Option<Offscreen>,
     pub queue: WGPUQueue,
     pub pipelines: Vec<WGPURenderPipeline>,
     pub pipeline_layouts: Vec<WGPUPipelineLayout>,
     pub render_pass: WGPURenderPassEncoder,
+    pub samplers: Vec<WGPUSampler>,
     pub shaders: Vec<WGPUShaderModule>,
     pub surface: WGPUSurface,
     pub swap_chain: WGPUSwapChain,
+    pub text_event: crate::window::TextEvent,
     pub textures: Vec<WGPUTexture>,
     pub texture_views: Vec<WGPUTextureView>,
     pub window: Option<Window>,
@@ -156,11 +181,46 @@ impl System {
             ..Default::default()
         }
     }
+
+    pub fn headless(size: PhysicalSize<u32>) -> System {
+        System {
+            offscreen: Some(Offscreen {
+                format: native::WGPUTextureFormat_RGBA8Unorm,
+                size,
+                ..Default::default()
+            }),
+            // First dedicated to swap chain texture view.
+            texture_views: vec![WGPUTextureView(null_mut())],
+            ..Default::default()
+        }
+    }
+
+    pub fn has_swap_chain(&self) -> bool {
+        match &self.offscreen {
+            Some(offscreen) => !offscreen.texture.0.is_null(),
+            None => !self.swap_chain.0.is_null(),
+        }
+    }
+
+    pub fn inner_size(&self) -> PhysicalSize<u32> {
+        match &self.window {
+            Some(window) => window.inner_size(),
+            None => self.offscreen.as_ref().unwrap().size,
+        }
+    }
+}
+
+/// Stands in for the window surface and swap chain when headless.
+#[derive(Default)]
+pub struct Offscreen {
+    pub format: native::WGPUTextureFormat,
+    pub size: PhysicalSize<u32>,
+    pub texture: WGPUTexture,
 }
 
 use std::ptr::null_mut;
 use wasmer::{Memory, Table};
 use wgpu_native::native;
-use winit::window::Window;
+use winit::{dpi::PhysicalSize, window::Window};
 
-use crate::gpu::SimpleGpu;
+use crate::{capture::FrameCapture, gamepad::Gamepads, gpu::SimpleGpu};
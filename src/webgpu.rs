@@ -100,10 +100,23 @@ struct WasmWGPUQueueDescriptor {
     label: WasmPtr<u8>,
 }
 
+/// Optional features to request whenever the adapter has them.
+const WANTED_FEATURES: [native::WGPUFeatureName; 3] = [
+    native::WGPUFeatureName_TextureCompressionBC,
+    native::WGPUFeatureName_TextureCompressionETC2,
+    native::WGPUFeatureName_TextureCompressionASTC,
+];
+
 pub fn wgpu_adapter_ensure_device_simple(system: &mut System) -> bool {
     let needed = system.device.0.is_null();
     if needed {
         let adapter = system.adapter.0;
+        let features: Vec<_> = WANTED_FEATURES
+            .into_iter()
+            .filter(|&feature| unsafe {
+                wgpu_native::device::wgpuAdapterHasFeature(adapter, feature)
+            })
+            .collect();
         unsafe {
             let required_limits = native::WGPURequiredLimits {
                 nextInChain: null(),
@@ -115,8 +128,8 @@ pub fn wgpu_adapter_ensure_device_simple(system: &mut System) -> bool {
                 Some(&native::WGPUDeviceDescriptor {
                     nextInChain: null(),
                     label: null(),
-                    requiredFeaturesCount: 0,
-                    requiredFeatures: null(),
+                    requiredFeaturesCount: features.len() as u32,
+                    requiredFeatures: features.as_ptr(),
                     requiredLimits: &required_limits as *const native::WGPURequiredLimits,
                     defaultQueue: native::WGPUQueueDescriptor {
                         nextInChain: null(),
@@ -148,6 +161,11 @@ pub fn wgpu_adapter_ensure_device_simple(system: &mut System) -> bool {
     needed
 }
 
+pub fn wgpu_adapter_has_feature(env: FunctionEnvMut<System>, _adapter: u32, feature: u32) -> u32 {
+    let system = env.data();
+    unsafe { wgpu_native::device::wgpuAdapterHasFeature(system.adapter.0, feature) as u32 }
+}
+
 pub fn wgpu_adapter_request_device(
     mut env: FunctionEnvMut<System>,
     adapter: u32,
@@ -216,13 +234,24 @@ struct WasmWGPURenderPassColorAttachment {
 
 #[derive(Copy, Clone, Debug, ValueType)]
 #[repr(C)]
-struct WasmWGPUColor {
+pub struct WasmWGPUColor {
     r: f64,
     g: f64,
     b: f64,
     a: f64,
 }
 
+impl WasmWGPUColor {
+    pub fn to_native(&self) -> native::WGPUColor {
+        native::WGPUColor {
+            r: self.r,
+            g: self.g,
+            b: self.b,
+            a: self.a,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, ValueType)]
 #[repr(C)]
 struct WasmWGPURenderPassDepthStencilAttachment {
@@ -237,6 +266,28 @@ struct WasmWGPURenderPassDepthStencilAttachment {
     stencil_read_only: bool,
 }
 
+pub fn wgpu_command_encoder_begin_compute_pass(
+    mut env: FunctionEnvMut<System>,
+    _encoder: u32,
+    _descriptor: u32,
+) -> u32 {
+    let system = env.data_mut();
+    if system.compute_pass.0.is_null() {
+        system.compute_pass.0 = unsafe {
+            wgpu_native::command::wgpuCommandEncoderBeginComputePass(
+                system.encoder.0,
+                Some(&native::WGPUComputePassDescriptor {
+                    nextInChain: null(),
+                    label: null(),
+                    timestampWriteCount: 0,
+                    timestampWrites: null(),
+                }),
+            )
+        };
+    }
+    1
+}
+
 pub fn wgpu_command_encoder_begin_render_pass(
     mut env: FunctionEnvMut<System>,
     _encoder: u32,
@@ -261,12 +312,7 @@ pub fn wgpu_command_encoder_begin_render_pass(
                     resolveTarget: std::ptr::null_mut(),
                     loadOp: attachment.load_op,
                     storeOp: attachment.store_op,
-                    clearValue: native::WGPUColor {
-                        r: attachment.clear_value.r,
-                        g: attachment.clear_value.g,
-                        b: attachment.clear_value.b,
-                        a: attachment.clear_value.a,
-                    },
+                    clearValue: attachment.clear_value.to_native(),
                 }
             })
             .collect();
@@ -325,6 +371,68 @@ pub fn wgpu_command_encoder_finish(
     1
 }
 
+pub fn wgpu_compute_pass_encoder_dispatch_workgroups(
+    env: FunctionEnvMut<System>,
+    _compute_pass: u32,
+    x: u32,
+    y: u32,
+    z: u32,
+) {
+    let system = env.data();
+    unsafe {
+        wgpu_native::command::wgpuComputePassEncoderDispatchWorkgroups(
+            system.compute_pass.0,
+            x,
+            y,
+            z,
+        );
+    }
+}
+
+pub fn wgpu_compute_pass_encoder_end(mut env: FunctionEnvMut<System>, _compute_pass: u32) {
+    let system = env.data_mut();
+    if !system.compute_pass.0.is_null() {
+        unsafe {
+            wgpu_native::command::wgpuComputePassEncoderEnd(system.compute_pass.0);
+        }
+        system.compute_pass.0 = null_mut();
+    }
+}
+
+pub fn wgpu_compute_pass_encoder_set_bind_group(
+    env: FunctionEnvMut<System>,
+    _compute_pass: u32,
+    group_index: u32,
+    group: u32,
+    _dynamic_offset_count: u32,
+    _dynamic_offsets: u32, // uint32_t const *
+) {
+    let system = env.data();
+    unsafe {
+        wgpu_native::command::wgpuComputePassEncoderSetBindGroup(
+            system.compute_pass.0,
+            group_index,
+            system.bind_groups[group as usize - 1].0,
+            0,
+            null(),
+        );
+    }
+}
+
+pub fn wgpu_compute_pass_encoder_set_pipeline(
+    env: FunctionEnvMut<System>,
+    _compute_pass: u32,
+    pipeline: u32,
+) {
+    let system = env.data();
+    unsafe {
+        wgpu_native::command::wgpuComputePassEncoderSetPipeline(
+            system.compute_pass.0,
+            system.compute_pipelines[pipeline as usize - 1].0,
+        );
+    }
+}
+
 pub fn wgpu_ensure_instance_simple(system: &mut System) {
     if system.instance.0.is_null() {
         system.instance.0 = unsafe {
@@ -389,7 +497,10 @@ pub fn wgpu_device_create_bind_group(
                 },
                 offset: entry.offset,
                 size: entry.size,
-                sampler: null_mut(),
+                sampler: match entry.sampler {
+                    0 => null_mut(),
+                    _ => system.samplers[entry.sampler as usize - 1].0,
+                },
                 textureView: match entry.texture_view {
                     0 => null_mut(),
                     _ => system.texture_views[entry.texture_view as usize - 1].0,
@@ -590,6 +701,63 @@ pub fn wgpu_device_create_command_encoder(
     1
 }
 
+#[derive(Copy, Clone, Debug, ValueType)]
+#[repr(C)]
+struct WasmWGPUComputePipelineDescriptor {
+    next_in_chain: WasmPtr<WasmWGPUChainedStruct>,
+    label: WasmPtr<u8>,
+    layout: u32, // WGPUPipelineLayout
+    compute: WasmWGPUProgrammableStageDescriptor,
+}
+
+#[derive(Copy, Clone, Debug, ValueType)]
+#[repr(C)]
+struct WasmWGPUProgrammableStageDescriptor {
+    next_in_chain: WasmPtr<WasmWGPUChainedStruct>,
+    module: u32, // WGPUShaderModule
+    entry_point: WasmPtr<u8>,
+    constant_count: u32,
+    constants: u32, // WGPUConstantEntry const *
+}
+
+pub fn wgpu_device_create_compute_pipeline(
+    mut env: FunctionEnvMut<System>,
+    device: u32,
+    descriptor: u32,
+) -> u32 {
+    println!("wgpuDeviceCreateComputePipeline({device}, {descriptor})");
+    let (system, store) = env.data_and_store_mut();
+    let memory = system.memory.as_ref().unwrap().view(&store);
+    let descriptor = WasmPtr::<WasmWGPUComputePipelineDescriptor>::new(descriptor)
+        .read(&memory)
+        .unwrap();
+    let entry_point = read_cstring(descriptor.compute.entry_point, &memory).unwrap();
+    let pipeline = unsafe {
+        wgpu_native::device::wgpuDeviceCreateComputePipeline(
+            system.device.0,
+            Some(&native::WGPUComputePipelineDescriptor {
+                nextInChain: null(),
+                label: null(),
+                layout: match descriptor.layout {
+                    // Auto layout from the shader.
+                    0 => null_mut(),
+                    layout => system.pipeline_layouts[layout as usize - 1].0,
+                },
+                compute: native::WGPUProgrammableStageDescriptor {
+                    nextInChain: null(),
+                    module: system.shaders[descriptor.compute.module as usize - 1].0,
+                    entryPoint: entry_point.as_ptr(),
+                    constantCount: 0,
+                    constants: null(),
+                },
+            }),
+        )
+    };
+    assert_ne!(null(), pipeline);
+    system.compute_pipelines.push(WGPUComputePipeline(pipeline));
+    system.compute_pipelines.len().try_into().unwrap()
+}
+
 #[derive(Copy, Clone, Debug, ValueType)]
 #[repr(C)]
 struct WasmWGPUPipelineLayoutDescriptor {
@@ -750,6 +918,45 @@ struct WasmWGPUFragmentState {
     targets: WasmPtr<WasmWGPUColorTargetState>,
 }
 
+#[derive(Copy, Clone, Debug, ValueType)]
+#[repr(C)]
+struct WasmWGPUBlendComponent {
+    operation: native::WGPUBlendOperation,
+    src_factor: native::WGPUBlendFactor,
+    dst_factor: native::WGPUBlendFactor,
+}
+
+impl WasmWGPUBlendComponent {
+    fn to_native(&self) -> native::WGPUBlendComponent {
+        native::WGPUBlendComponent {
+            operation: self.operation,
+            srcFactor: self.src_factor,
+            dstFactor: self.dst_factor,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, ValueType)]
+#[repr(C)]
+struct WasmWGPUBlendState {
+    color: WasmWGPUBlendComponent,
+    alpha: WasmWGPUBlendComponent,
+}
+
+/// Reads an optional WGPUBlendState const *.
+pub fn read_blend_state(blend: u32, memory: &MemoryView) -> Option<native::WGPUBlendState> {
+    if blend == 0 {
+        return None;
+    }
+    let blend = WasmPtr::<WasmWGPUBlendState>::new(blend)
+        .read(memory)
+        .unwrap();
+    Some(native::WGPUBlendState {
+        color: blend.color.to_native(),
+        alpha: blend.alpha.to_native(),
+    })
+}
+
 #[derive(Copy, Clone, Debug, ValueType)]
 #[repr(C)]
 struct WasmWGPUColorTargetState {
@@ -772,19 +979,29 @@ pub fn wgpu_device_create_render_pipeline(
     let vertex_entry_point = read_cstring(descriptor.vertex.entry_point, &memory).unwrap();
     let fragment = descriptor.fragment.read(&memory).unwrap();
     let fragment_entry_point = read_cstring(fragment.entry_point, &memory).unwrap();
-    let fragment_targets: Vec<_> = fragment
+    let targets: Vec<_> = fragment
         .targets
         .slice(&memory, fragment.target_count)
         .unwrap()
         .iter()
-        .map(|target| {
-            let target = target.read().unwrap();
-            native::WGPUColorTargetState {
-                nextInChain: null(),
-                format: target.format,
-                blend: null(),
-                writeMask: target.write_mask,
-            }
+        .map(|target| target.read().unwrap())
+        .collect();
+    // Keep blends alive outside the targets that point to them.
+    let blends: Vec<_> = targets
+        .iter()
+        .map(|target| read_blend_state(target.blend, &memory))
+        .collect();
+    let fragment_targets: Vec<_> = targets
+        .iter()
+        .zip(&blends)
+        .map(|(target, blend)| native::WGPUColorTargetState {
+            nextInChain: null(),
+            format: target.format,
+            blend: match blend {
+                Some(blend) => blend,
+                None => null(),
+            },
+            writeMask: target.write_mask,
         })
         .collect();
     let mut vertex_attributes: Vec<Vec<_>> = vec![];
@@ -876,6 +1093,79 @@ pub fn wgpu_device_create_render_pipeline(
     system.pipelines.len().try_into().unwrap()
 }
 
+#[derive(Copy, Clone, Debug, ValueType)]
+#[repr(C)]
+pub struct WasmWGPUSamplerDescriptor {
+    next_in_chain: WasmPtr<WasmWGPUChainedStruct>,
+    label: WasmPtr<u8>,
+    address_mode_u: native::WGPUAddressMode,
+    address_mode_v: native::WGPUAddressMode,
+    address_mode_w: native::WGPUAddressMode,
+    pub mag_filter: native::WGPUFilterMode,
+    pub min_filter: native::WGPUFilterMode,
+    pub mipmap_filter: native::WGPUMipmapFilterMode,
+    lod_min_clamp: f32,
+    lod_max_clamp: f32,
+    pub compare: native::WGPUCompareFunction,
+    max_anisotropy: u16,
+}
+
+impl WasmWGPUSamplerDescriptor {
+    /// Linear filtering with clamped addressing, as common for sprites.
+    pub fn linear() -> WasmWGPUSamplerDescriptor {
+        WasmWGPUSamplerDescriptor {
+            next_in_chain: WasmPtr::null(),
+            label: WasmPtr::null(),
+            address_mode_u: native::WGPUAddressMode_ClampToEdge,
+            address_mode_v: native::WGPUAddressMode_ClampToEdge,
+            address_mode_w: native::WGPUAddressMode_ClampToEdge,
+            mag_filter: native::WGPUFilterMode_Linear,
+            min_filter: native::WGPUFilterMode_Linear,
+            mipmap_filter: native::WGPUMipmapFilterMode_Linear,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 32.0,
+            compare: native::WGPUCompareFunction_Undefined,
+            max_anisotropy: 1,
+        }
+    }
+
+    pub fn to_native(&self) -> native::WGPUSamplerDescriptor {
+        native::WGPUSamplerDescriptor {
+            nextInChain: null(),
+            label: null(),
+            addressModeU: self.address_mode_u,
+            addressModeV: self.address_mode_v,
+            addressModeW: self.address_mode_w,
+            magFilter: self.mag_filter,
+            minFilter: self.min_filter,
+            mipmapFilter: self.mipmap_filter,
+            lodMinClamp: self.lod_min_clamp,
+            lodMaxClamp: self.lod_max_clamp,
+            compare: self.compare,
+            // Zero isn't valid, but it's easy to leave zeroed.
+            maxAnisotropy: self.max_anisotropy.max(1),
+        }
+    }
+}
+
+pub fn wgpu_device_create_sampler(
+    mut env: FunctionEnvMut<System>,
+    device: u32,
+    descriptor: u32,
+) -> u32 {
+    println!("wgpuDeviceCreateSampler({device}, {descriptor})");
+    let (system, store) = env.data_and_store_mut();
+    let view = system.memory.as_ref().unwrap().view(&store);
+    let descriptor = WasmRef::<WasmWGPUSamplerDescriptor>::new(&view, descriptor as u64)
+        .read()
+        .unwrap();
+    let sampler = unsafe {
+        wgpu_native::device::wgpuDeviceCreateSampler(system.device.0, Some(&descriptor.to_native()))
+    };
+    system.samplers.push(WGPUSampler(sampler));
+    system.samplers.len().try_into().unwrap()
+}
+
 #[derive(Copy, Clone, Debug, ValueType)]
 #[repr(C)]
 struct WasmWGPUChainedStruct {
@@ -957,13 +1247,12 @@ pub fn wgpu_device_create_shader_module_simple(system: &mut System, wgsl: &CStr)
     system.shaders.len().try_into().unwrap()
 }
 
-pub fn read_cstring(
-    pointer: WasmPtr<u8>,
-    memory: &MemoryView,
-) -> Result<CString, FromVecWithNulError> {
-    let mut bytes = pointer.read_until(&memory, |c| *c == 0).unwrap();
+pub fn read_cstring(pointer: WasmPtr<u8>, memory: &MemoryView) -> Result<CString, RuntimeError> {
+    let mut bytes = pointer
+        .read_until(&memory, |c| *c == 0)
+        .map_err(|err| RuntimeError::new(err.to_string()))?;
     bytes.push(0);
-    CString::from_vec_with_nul(bytes)
+    CString::from_vec_with_nul(bytes).map_err(|err| RuntimeError::new(err.to_string()))
 }
 
 #[derive(Copy, Clone, Debug, ValueType)]
@@ -978,6 +1267,48 @@ struct WasmWGPUSwapChainDescriptor {
     present_mode: native::WGPUPresentMode,
 }
 
+pub fn wgpu_device_create_swap_chain_simple(
+    system: &mut System,
+    descriptor: &native::WGPUSwapChainDescriptor,
+) {
+    match system.offscreen.as_mut() {
+        Some(offscreen) => {
+            offscreen.format = descriptor.format;
+            offscreen.texture.0 = unsafe {
+                wgpu_native::device::wgpuDeviceCreateTexture(
+                    system.device.0,
+                    Some(&native::WGPUTextureDescriptor {
+                        nextInChain: null(),
+                        label: null(),
+                        // Copy source for reading back frames.
+                        usage: descriptor.usage | native::WGPUTextureUsage_CopySrc,
+                        dimension: native::WGPUTextureDimension_2D,
+                        size: native::WGPUExtent3D {
+                            width: descriptor.width,
+                            height: descriptor.height,
+                            depthOrArrayLayers: 1,
+                        },
+                        format: descriptor.format,
+                        mipLevelCount: 1,
+                        sampleCount: 1,
+                        viewFormatCount: 0,
+                        viewFormats: null(),
+                    }),
+                )
+            };
+        }
+        None => {
+            system.swap_chain.0 = unsafe {
+                wgpu_native::device::wgpuDeviceCreateSwapChain(
+                    system.device.0,
+                    system.surface.0,
+                    Some(descriptor),
+                )
+            };
+        }
+    }
+}
+
 pub fn wgpu_device_create_swap_chain(
     mut env: FunctionEnvMut<System>,
     _device: u32,
@@ -985,26 +1316,23 @@ pub fn wgpu_device_create_swap_chain(
     descriptor: u32,
 ) -> u32 {
     let (system, store) = env.data_and_store_mut();
-    if system.swap_chain.0.is_null() {
+    if !system.has_swap_chain() {
         let memory = system.memory.as_ref().unwrap().view(&store);
         let descriptor = WasmRef::<WasmWGPUSwapChainDescriptor>::new(&memory, descriptor as u64)
             .read()
             .unwrap();
-        system.swap_chain.0 = unsafe {
-            wgpu_native::device::wgpuDeviceCreateSwapChain(
-                system.device.0,
-                system.surface.0,
-                Some(&native::WGPUSwapChainDescriptor {
-                    nextInChain: null(),
-                    label: null(),
-                    usage: descriptor.usage,
-                    format: descriptor.format,
-                    width: descriptor.width,
-                    height: descriptor.height,
-                    presentMode: descriptor.present_mode,
-                }),
-            )
-        };
+        wgpu_device_create_swap_chain_simple(
+            system,
+            &native::WGPUSwapChainDescriptor {
+                nextInChain: null(),
+                label: null(),
+                usage: descriptor.usage,
+                format: descriptor.format,
+                width: descriptor.width,
+                height: descriptor.height,
+                presentMode: descriptor.present_mode,
+            },
+        );
     }
     1
 }
@@ -1133,9 +1461,11 @@ pub fn wgpu_device_set_uncaptured_error_callback(
 
 pub fn wgpu_instance_ensure_surface_simple(system: &mut System) {
     if system.surface.0.is_null() {
-        system.surface.0 = unsafe {
-            wgpu_instance_create_surface_any(system.instance.0, system.window.as_ref().unwrap())
-        };
+        // Headless has no window to make a surface for.
+        if let Some(window) = system.window.as_ref() {
+            system.surface.0 =
+                unsafe { wgpu_instance_create_surface_any(system.instance.0, window) };
+        }
     }
 }
 
@@ -1217,7 +1547,8 @@ pub fn wgpu_instance_ensure_adapter_simple(system: &mut System) {
                     nextInChain: null(),
                     compatibleSurface: system.surface.0,
                     powerPreference: native::WGPUPowerPreference_Undefined,
-                    forceFallbackAdapter: false,
+                    // Headless is meant for machines without a real gpu.
+                    forceFallbackAdapter: system.offscreen.is_some(),
                 }),
                 Some(request_adapter_callback),
                 system as *mut System as *mut std::ffi::c_void,
@@ -1536,7 +1867,12 @@ pub fn wgpu_surface_drop(_env: FunctionEnvMut<System>, surface: u32) {
 }
 
 pub fn wgpu_surface_get_preferred_format_simple(system: &System) -> native::WGPUTextureFormat {
-    unsafe { wgpu_native::wgpuSurfaceGetPreferredFormat(system.surface.0, system.adapter.0) }
+    match &system.offscreen {
+        Some(offscreen) => offscreen.format,
+        None => unsafe {
+            wgpu_native::wgpuSurfaceGetPreferredFormat(system.surface.0, system.adapter.0)
+        },
+    }
 }
 
 pub fn wgpu_surface_get_preferred_format(
@@ -1552,6 +1888,14 @@ pub fn wgpu_surface_get_preferred_format(
 pub fn wgpu_swap_chain_drop_simple(system: &mut System) {
     // For good measure, ensure null view also.
     system.texture_views[0].0 = null_mut();
+    if let Some(offscreen) = system.offscreen.as_mut() {
+        if !offscreen.texture.0.is_null() {
+            unsafe {
+                wgpu_native::device::wgpuTextureDrop(offscreen.texture.0);
+            }
+            offscreen.texture.0 = null_mut();
+        }
+    }
     if !system.swap_chain.0.is_null() {
         unsafe {
             wgpu_native::device::wgpuSwapChainDrop(system.swap_chain.0);
@@ -1573,20 +1917,60 @@ pub fn wgpu_swap_chain_get_current_texture_view(
     // println!("wgpuSwapChainGetCurrentTextureView({swap_chain})");
     let system = env.data_mut();
     if system.texture_views[0].0.is_null() {
-        system.texture_views[0].0 =
-            unsafe { wgpu_native::device::wgpuSwapChainGetCurrentTextureView(system.swap_chain.0) };
+        system.texture_views[0].0 = wgpu_swap_chain_get_current_texture_view_simple(system);
     }
     1
 }
 
-pub fn wgpu_swap_chain_present(mut env: FunctionEnvMut<System>, _swap_chain: u32) {
-    // println!("wgpuSwapChainPresent({_swap_chain})");
-    let system = env.data_mut();
-    if !system.swap_chain.0.is_null() {
-        unsafe {
-            wgpu_native::device::wgpuSwapChainPresent(system.swap_chain.0);
+pub fn wgpu_swap_chain_get_current_texture_view_simple(system: &System) -> native::WGPUTextureView {
+    match &system.offscreen {
+        Some(offscreen) => unsafe {
+            wgpu_native::device::wgpuTextureCreateView(
+                offscreen.texture.0,
+                Some(&native::WGPUTextureViewDescriptor {
+                    nextInChain: null(),
+                    label: null(),
+                    format: offscreen.format,
+                    dimension: native::WGPUTextureViewDimension_2D,
+                    baseMipLevel: 0,
+                    mipLevelCount: 1,
+                    baseArrayLayer: 0,
+                    arrayLayerCount: 1,
+                    aspect: native::WGPUTextureAspect_All,
+                }),
+            )
+        },
+        None => unsafe {
+            wgpu_native::device::wgpuSwapChainGetCurrentTextureView(system.swap_chain.0)
+        },
+    }
+}
+
+pub fn wgpu_swap_chain_present_simple(system: &mut System) -> Result<(), RuntimeError> {
+    match system.offscreen {
+        // Offscreen textures just stay put for whoever wants them.
+        Some(_) => {
+            if system.capture.is_some() {
+                capture_frame(system).map_err(|err| RuntimeError::new(format!("{err:#}")))?;
+            }
+        }
+        None => {
+            if !system.swap_chain.0.is_null() {
+                unsafe {
+                    wgpu_native::device::wgpuSwapChainPresent(system.swap_chain.0);
+                }
+            }
         }
     }
+    Ok(())
+}
+
+pub fn wgpu_swap_chain_present(
+    mut env: FunctionEnvMut<System>,
+    _swap_chain: u32,
+) -> Result<(), RuntimeError> {
+    // println!("wgpuSwapChainPresent({_swap_chain})");
+    wgpu_swap_chain_present_simple(env.data_mut())
 }
 
 #[derive(Copy, Clone, Debug, ValueType)]
@@ -1659,12 +2043,12 @@ pub fn wgpu_texture_view_drop(mut env: FunctionEnvMut<System>, texture_view: u32
     }
 }
 
-use crate::system::*;
+use crate::{capture::capture_frame, system::*};
 use std::{
-    ffi::{CStr, CString, FromVecWithNulError},
+    ffi::{CStr, CString},
     mem::MaybeUninit,
     ptr::{null, null_mut},
 };
-use wasmer::{FunctionEnvMut, MemoryView, Value, ValueType, WasmPtr, WasmRef};
+use wasmer::{FunctionEnvMut, MemoryView, RuntimeError, Value, ValueType, WasmPtr, WasmRef};
 use wgpu_native::native::{self, WGPULimits};
 use winit::window::Window;
@@ -1,27 +1,19 @@
-pub fn run_loop(event_loop: EventLoop<()>, mut store: Store, env: FunctionEnv<System>) {
+pub fn run_loop(
+    event_loop: EventLoop<()>,
+    mut store: Store,
+    env: FunctionEnv<System>,
+    mut gamepads: Option<Box<dyn GamepadSource>>,
+) {
     // let (system, mut store) = env.data_and_store_mut();
     // let window = system.window.as_ref().unwrap();
     let mut modifiers: ModifiersState = ModifiersState::empty();
+    // Winit doesn't flag auto repeat, so track what's already down.
+    let mut held_keys: HashSet<VirtualKeyCode> = HashSet::new();
+    let mut text_input = TextInput::default();
     event_loop.run(move |event, _, control_flow| {
         let mut system = env.clone().as_mut(&mut store);
         let window = system.window.as_ref().unwrap();
-        let window_listen = system.window_listen.as_ref().unwrap().clone();
-        let window_listen_userdata = system.window_listen_userdata;
-        let send_event =
-            |store: &mut Store, function: &Function, event_type: WindowEventType, userdata: u32| {
-                let mut system = env.as_mut(store);
-                gpu_window_listen(&mut system, event_type);
-                function
-                    .call(
-                        store,
-                        &[
-                            // TODO How to put u32 into here? How to just let it wrap?
-                            Value::I32((event_type as u32).try_into().unwrap()),
-                            Value::I32(userdata.try_into().unwrap()),
-                        ],
-                    )
-                    .unwrap();
-            };
+        let mut result = Ok(());
         match event {
             Event::WindowEvent {
                 ref event,
@@ -30,12 +22,7 @@ pub fn run_loop(event_loop: EventLoop<()>, mut store: Store, env: FunctionEnv<Sy
                 // if !state.input(event) {
                 match event {
                     WindowEvent::CloseRequested => {
-                        send_event(
-                            &mut store,
-                            &window_listen,
-                            WindowEventType::Close,
-                            window_listen_userdata,
-                        );
+                        result = send_event(&mut store, &env, WindowEventType::Close);
                         *control_flow = ControlFlow::Exit;
                     }
                     WindowEvent::KeyboardInput {
@@ -56,48 +43,72 @@ pub fn run_loop(event_loop: EventLoop<()>, mut store: Store, env: FunctionEnv<Sy
                                 None => Some(winit::window::Fullscreen::Borderless(None)),
                             })
                         } else {
+                            let pressed = *state == ElementState::Pressed;
+                            let repeat = match pressed {
+                                true => !held_keys.insert(*key),
+                                false => {
+                                    held_keys.remove(key);
+                                    false
+                                }
+                            };
                             system.key_event = Some(KeyEvent {
                                 code: convert_key(*key),
-                                pressed: *state == ElementState::Pressed,
+                                modifiers: convert_modifiers(modifiers),
+                                pressed,
+                                repeat,
                             });
-                            send_event(
-                                &mut store,
-                                &window_listen,
-                                WindowEventType::Key,
-                                window_listen_userdata,
-                            );
+                            result = send_event(&mut store, &env, WindowEventType::Key);
+                        }
+                    }
+                    WindowEvent::ReceivedCharacter(c) => {
+                        if let Some(text_event) = text_input.char_event(*c) {
+                            system.text_event = text_event;
+                            result = send_event(&mut store, &env, WindowEventType::Text);
+                        }
+                    }
+                    WindowEvent::Ime(ime) => {
+                        if let Some(text_event) = text_input.ime_event(ime) {
+                            system.text_event = text_event;
+                            result = send_event(&mut store, &env, WindowEventType::Text);
                         }
                     }
                     WindowEvent::ModifiersChanged(state) => {
                         modifiers = *state;
                     }
+                    WindowEvent::Focused(false) => {
+                        // Releases while unfocused never arrive.
+                        held_keys.clear();
+                    }
+                    WindowEvent::CursorMoved { position, .. } => {
+                        system.mouse_event.position = [position.x as f32, position.y as f32];
+                        result = send_event(&mut store, &env, WindowEventType::MouseMove);
+                    }
+                    WindowEvent::MouseInput { state, button, .. } => {
+                        system.mouse_event.button = convert_mouse_button(*button);
+                        system.mouse_event.pressed = *state == ElementState::Pressed;
+                        result = send_event(&mut store, &env, WindowEventType::MouseButton);
+                    }
+                    WindowEvent::MouseWheel { delta, .. } => {
+                        (system.mouse_event.delta, system.mouse_event.delta_pixels) = match delta {
+                            MouseScrollDelta::LineDelta(x, y) => ([*x, *y], false),
+                            MouseScrollDelta::PixelDelta(position) => {
+                                ([position.x as f32, position.y as f32], true)
+                            }
+                        };
+                        result = send_event(&mut store, &env, WindowEventType::MouseWheel);
+                    }
                     WindowEvent::Resized(_physical_size) => {
-                        send_event(
-                            &mut store,
-                            &window_listen,
-                            WindowEventType::Resize,
-                            window_listen_userdata,
-                        );
+                        result = send_event(&mut store, &env, WindowEventType::Resize);
                     }
                     WindowEvent::ScaleFactorChanged { .. } => {
-                        send_event(
-                            &mut store,
-                            &window_listen,
-                            WindowEventType::Resize,
-                            window_listen_userdata,
-                        );
+                        result = send_event(&mut store, &env, WindowEventType::Resize);
                     }
                     _ => {}
                 }
                 // }
             }
             Event::RedrawRequested(window_id) if window_id == window.id() => {
-                send_event(
-                    &mut store,
-                    &window_listen,
-                    WindowEventType::Redraw,
-                    window_listen_userdata,
-                );
+                result = send_event(&mut store, &env, WindowEventType::Redraw);
                 // state.update();
                 // state.render();
                 // match state.render() {
@@ -110,6 +121,11 @@ pub fn run_loop(event_loop: EventLoop<()>, mut store: Store, env: FunctionEnv<Sy
                 //     Err(wgpu::SurfaceError::Timeout) => log::warn!("Surface timeout"),
                 // }
             }
+            Event::MainEventsCleared => {
+                if let Some(gamepads) = gamepads.as_deref_mut() {
+                    result = poll_gamepads(&mut store, &env, gamepads);
+                }
+            }
             Event::RedrawEventsCleared => {
                 // std::thread::sleep(std::time::Duration::from_secs_f64(1.0 / 60.0));
                 // RedrawRequested will only trigger once, unless we manually
@@ -119,9 +135,49 @@ pub fn run_loop(event_loop: EventLoop<()>, mut store: Store, env: FunctionEnv<Sy
             }
             _ => {}
         }
+        if let Err(err) = result {
+            eprintln!("App failed: {err}");
+            *control_flow = ControlFlow::ExitWithCode(1);
+        }
     });
 }
 
+/// Drives the app without a window, rendering offscreen for a fixed number of
+/// frames before closing.
+pub fn run_headless(
+    mut store: Store,
+    env: FunctionEnv<System>,
+    frames: u32,
+) -> Result<(), RuntimeError> {
+    for _ in 0..frames {
+        send_event(&mut store, &env, WindowEventType::Redraw)?;
+    }
+    send_event(&mut store, &env, WindowEventType::Close)
+}
+
+/// Calls the app's listener, failing if the app traps, such as from an
+/// import rejecting its arguments.
+pub fn send_event(
+    store: &mut Store,
+    env: &FunctionEnv<System>,
+    event_type: WindowEventType,
+) -> Result<(), RuntimeError> {
+    let mut system = env.as_mut(store);
+    gpu_window_listen(&mut system, event_type);
+    let function = system.window_listen.as_ref().unwrap().clone();
+    let userdata = system.window_listen_userdata;
+    function
+        .call(
+            store,
+            &[
+                // TODO How to put u32 into here? How to just let it wrap?
+                Value::I32((event_type as u32).try_into().unwrap()),
+                Value::I32(userdata.try_into().unwrap()),
+            ],
+        )
+        .map(|_| ())
+}
+
 #[derive(Debug, Hash, Ord, PartialOrd, PartialEq, Eq, Clone, Copy)]
 #[repr(u32)]
 pub enum WindowEventType {
@@ -129,43 +185,375 @@ pub enum WindowEventType {
     Key = 2,
     Redraw = 3,
     Resize = 4,
+    MouseMove = 5,
+    MouseButton = 6,
+    MouseWheel = 7,
+    Text = 8,
+    Gamepad = 9,
+}
+
+// Generates KeyCode along with name lookup and winit conversion, so the three
+// can't drift apart.
+macro_rules! key_codes {
+    ($($name:ident = $value:literal => $winit:ident,)*) => {
+        #[derive(Debug, Hash, Ord, PartialOrd, PartialEq, Eq, Clone, Copy)]
+        #[repr(u32)]
+        pub enum KeyCode {
+            Undefined = 0,
+            $($name = $value,)*
+        }
+
+        impl KeyCode {
+            pub fn from_name(name: &str) -> Option<KeyCode> {
+                match name {
+                    $(stringify!($name) => Some(KeyCode::$name),)*
+                    _ => None,
+                }
+            }
+        }
+
+        fn convert_key(wkey: VirtualKeyCode) -> KeyCode {
+            match wkey {
+                $(VirtualKeyCode::$winit => KeyCode::$name,)*
+                _ => KeyCode::Undefined,
+            }
+        }
+    };
+}
+
+key_codes! {
+    Left = 1 => Left,
+    Up = 2 => Up,
+    Right = 3 => Right,
+    Down = 4 => Down,
+    PageUp = 5 => PageUp,
+    PageDown = 6 => PageDown,
+    Home = 7 => Home,
+    End = 8 => End,
+    Insert = 9 => Insert,
+    Delete = 10 => Delete,
+    Backspace = 11 => Back,
+    Tab = 12 => Tab,
+    Enter = 13 => Return,
+    Escape = 14 => Escape,
+    Space = 15 => Space,
+    Digit0 = 16 => Key0,
+    Digit1 = 17 => Key1,
+    Digit2 = 18 => Key2,
+    Digit3 = 19 => Key3,
+    Digit4 = 20 => Key4,
+    Digit5 = 21 => Key5,
+    Digit6 = 22 => Key6,
+    Digit7 = 23 => Key7,
+    Digit8 = 24 => Key8,
+    Digit9 = 25 => Key9,
+    A = 26 => A,
+    B = 27 => B,
+    C = 28 => C,
+    D = 29 => D,
+    E = 30 => E,
+    F = 31 => F,
+    G = 32 => G,
+    H = 33 => H,
+    I = 34 => I,
+    J = 35 => J,
+    K = 36 => K,
+    L = 37 => L,
+    M = 38 => M,
+    N = 39 => N,
+    O = 40 => O,
+    P = 41 => P,
+    Q = 42 => Q,
+    R = 43 => R,
+    S = 44 => S,
+    T = 45 => T,
+    U = 46 => U,
+    V = 47 => V,
+    W = 48 => W,
+    X = 49 => X,
+    Y = 50 => Y,
+    Z = 51 => Z,
+    F1 = 52 => F1,
+    F2 = 53 => F2,
+    F3 = 54 => F3,
+    F4 = 55 => F4,
+    F5 = 56 => F5,
+    F6 = 57 => F6,
+    F7 = 58 => F7,
+    F8 = 59 => F8,
+    F9 = 60 => F9,
+    F10 = 61 => F10,
+    F11 = 62 => F11,
+    F12 = 63 => F12,
+    ShiftLeft = 64 => LShift,
+    ShiftRight = 65 => RShift,
+    ControlLeft = 66 => LControl,
+    ControlRight = 67 => RControl,
+    AltLeft = 68 => LAlt,
+    AltRight = 69 => RAlt,
+    SuperLeft = 70 => LWin,
+    SuperRight = 71 => RWin,
+    Minus = 72 => Minus,
+    Equal = 73 => Equals,
+    BracketLeft = 74 => LBracket,
+    BracketRight = 75 => RBracket,
+    Backslash = 76 => Backslash,
+    Semicolon = 77 => Semicolon,
+    Quote = 78 => Apostrophe,
+    Backquote = 79 => Grave,
+    Comma = 80 => Comma,
+    Period = 81 => Period,
+    Slash = 82 => Slash,
+    CapsLock = 83 => Capital,
+    NumLock = 84 => Numlock,
+    ScrollLock = 85 => Scroll,
+    PrintScreen = 86 => Snapshot,
+    Pause = 87 => Pause,
+    ContextMenu = 88 => Apps,
+    Numpad0 = 89 => Numpad0,
+    Numpad1 = 90 => Numpad1,
+    Numpad2 = 91 => Numpad2,
+    Numpad3 = 92 => Numpad3,
+    Numpad4 = 93 => Numpad4,
+    Numpad5 = 94 => Numpad5,
+    Numpad6 = 95 => Numpad6,
+    Numpad7 = 96 => Numpad7,
+    Numpad8 = 97 => Numpad8,
+    Numpad9 = 98 => Numpad9,
+    NumpadAdd = 99 => NumpadAdd,
+    NumpadSubtract = 100 => NumpadSubtract,
+    NumpadMultiply = 101 => NumpadMultiply,
+    NumpadDivide = 102 => NumpadDivide,
+    NumpadDecimal = 103 => NumpadDecimal,
+    NumpadEnter = 104 => NumpadEnter,
+}
+
+impl KeyCode {
+    /// The modifier bit held down by this key, if any.
+    pub fn modifier(self) -> u32 {
+        match self {
+            KeyCode::ShiftLeft | KeyCode::ShiftRight => KeyModifiers::SHIFT,
+            KeyCode::ControlLeft | KeyCode::ControlRight => KeyModifiers::CONTROL,
+            KeyCode::AltLeft | KeyCode::AltRight => KeyModifiers::ALT,
+            KeyCode::SuperLeft | KeyCode::SuperRight => KeyModifiers::SUPER,
+            _ => 0,
+        }
+    }
+}
+
+pub struct KeyModifiers;
+
+impl KeyModifiers {
+    pub const SHIFT: u32 = 1;
+    pub const CONTROL: u32 = 2;
+    pub const ALT: u32 = 4;
+    pub const SUPER: u32 = 8;
+}
+
+fn convert_modifiers(state: ModifiersState) -> u32 {
+    let mut modifiers = 0;
+    for (held, bit) in [
+        (state.shift(), KeyModifiers::SHIFT),
+        (state.ctrl(), KeyModifiers::CONTROL),
+        (state.alt(), KeyModifiers::ALT),
+        (state.logo(), KeyModifiers::SUPER),
+    ] {
+        if held {
+            modifiers |= bit;
+        }
+    }
+    modifiers
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    /// Bits from KeyModifiers.
+    pub modifiers: u32,
+    pub pressed: bool,
+    /// Auto repeat from holding the key down.
+    pub repeat: bool,
 }
 
 #[derive(Debug, Hash, Ord, PartialOrd, PartialEq, Eq, Clone, Copy)]
 #[repr(u32)]
-pub enum KeyCode {
+pub enum MouseButton {
     Undefined = 0,
     Left = 1,
-    Up = 2,
-    Right = 3,
-    Down = 4,
-    PageUp = 5,
-    PageDown = 6,
+    Right = 2,
+    Middle = 3,
+    // Extra buttons count up from here.
+    Other = 4,
 }
 
-#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
-pub struct KeyEvent {
-    code: KeyCode,
+impl MouseButton {
+    pub fn from_name(name: &str) -> Option<u32> {
+        match name {
+            "Left" => Some(MouseButton::Left as u32),
+            "Right" => Some(MouseButton::Right as u32),
+            "Middle" => Some(MouseButton::Middle as u32),
+            _ => name
+                .parse::<u32>()
+                .ok()
+                .map(|index| MouseButton::Other as u32 + index),
+        }
+    }
+}
+
+/// Latest mouse state, with position in physical pixels.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct MouseEvent {
+    pub button: u32,
+    pub delta: [f32; 2],
+    pub delta_pixels: bool,
+    pub position: [f32; 2],
+    pub pressed: bool,
+}
+
+fn convert_mouse_button(button: winit::event::MouseButton) -> u32 {
+    match button {
+        winit::event::MouseButton::Left => MouseButton::Left as u32,
+        winit::event::MouseButton::Right => MouseButton::Right as u32,
+        winit::event::MouseButton::Middle => MouseButton::Middle as u32,
+        winit::event::MouseButton::Other(index) => MouseButton::Other as u32 + index as u32,
+    }
+}
+
+#[derive(Copy, Clone, Debug, ValueType)]
+#[repr(C)]
+struct WasmVec2f {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Copy, Clone, Debug, ValueType)]
+#[repr(C)]
+struct WasmMouseEvent {
+    position: WasmVec2f,
+    delta: WasmVec2f,
+    button: u32,
     pressed: bool,
+    delta_pixels: bool,
+}
+
+pub fn taca_mouse_event(mut env: FunctionEnvMut<System>, result: u32) {
+    let (system, mut store) = env.data_and_store_mut();
+    let view = system.memory.as_ref().unwrap().view(&mut store);
+    let mouse_event = system.mouse_event;
+    let mouse_event = WasmMouseEvent {
+        position: WasmVec2f {
+            x: mouse_event.position[0],
+            y: mouse_event.position[1],
+        },
+        delta: WasmVec2f {
+            x: mouse_event.delta[0],
+            y: mouse_event.delta[1],
+        },
+        button: mouse_event.button,
+        pressed: mouse_event.pressed,
+        delta_pixels: mouse_event.delta_pixels,
+    };
+    WasmRef::<WasmMouseEvent>::new(&view, result as u64)
+        .write(mouse_event)
+        .unwrap();
+}
+
+/// Typed or committed text, or in-progress IME composition if preedit.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct TextEvent {
+    pub text: String,
+    pub preedit: bool,
+    /// Byte range of the preedit cursor, if shown.
+    pub cursor: Option<(usize, usize)>,
 }
 
-fn convert_key(wkey: VirtualKeyCode) -> KeyCode {
-    match wkey {
-        VirtualKeyCode::Down => KeyCode::Down,
-        VirtualKeyCode::Left => KeyCode::Left,
-        VirtualKeyCode::PageDown => KeyCode::PageDown,
-        VirtualKeyCode::PageUp => KeyCode::PageUp,
-        VirtualKeyCode::Right => KeyCode::Right,
-        VirtualKeyCode::Up => KeyCode::Up,
-        _ => KeyCode::Undefined,
+/// Picks which of winit's char and IME events become text events, so chars
+/// from an IME composition don't also arrive as plain text.
+#[derive(Default)]
+struct TextInput {
+    composing: bool,
+}
+
+impl TextInput {
+    fn char_event(&self, c: char) -> Option<TextEvent> {
+        // Editing keys like backspace and enter come as key events instead.
+        match self.composing || c.is_control() {
+            true => None,
+            false => Some(TextEvent {
+                text: c.to_string(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn ime_event(&mut self, ime: &Ime) -> Option<TextEvent> {
+        match ime {
+            Ime::Preedit(text, cursor) => {
+                self.composing = !text.is_empty();
+                Some(TextEvent {
+                    text: text.clone(),
+                    preedit: true,
+                    cursor: *cursor,
+                })
+            }
+            Ime::Commit(text) => {
+                self.composing = false;
+                Some(TextEvent {
+                    text: text.clone(),
+                    ..Default::default()
+                })
+            }
+            Ime::Enabled => None,
+            Ime::Disabled => {
+                self.composing = false;
+                None
+            }
+        }
     }
 }
 
+#[derive(Copy, Clone, Debug, ValueType)]
+#[repr(C)]
+struct WasmTextEvent {
+    size: u32,
+    cursor_start: i32,
+    cursor_end: i32,
+    preedit: bool,
+}
+
+pub fn taca_text_event(mut env: FunctionEnvMut<System>, result: u32, buffer: u32, capacity: u32) {
+    let (system, mut store) = env.data_and_store_mut();
+    let view = system.memory.as_ref().unwrap().view(&mut store);
+    let text_event = &system.text_event;
+    // Copy only whole chars, and let size tell if the buffer was too small.
+    let mut copied = text_event.text.len().min(capacity as usize);
+    while !text_event.text.is_char_boundary(copied) {
+        copied -= 1;
+    }
+    view.write(buffer as u64, &text_event.text.as_bytes()[..copied])
+        .unwrap();
+    let (cursor_start, cursor_end) = match text_event.cursor {
+        Some((start, end)) => (start as i32, end as i32),
+        None => (-1, -1),
+    };
+    let text_event = WasmTextEvent {
+        size: text_event.text.len() as u32,
+        cursor_start,
+        cursor_end,
+        preedit: text_event.preedit,
+    };
+    WasmRef::<WasmTextEvent>::new(&view, result as u64)
+        .write(text_event)
+        .unwrap();
+}
+
 #[derive(Copy, Clone, Debug, ValueType)]
 #[repr(C)]
 struct WasmKeyEvent {
     code: u32,
     pressed: bool,
+    repeat: bool,
+    modifiers: u32,
 }
 
 pub fn taca_key_event(mut env: FunctionEnvMut<System>, result: u32) {
@@ -176,10 +564,14 @@ pub fn taca_key_event(mut env: FunctionEnvMut<System>, result: u32) {
         Some(key_event) => WasmKeyEvent {
             code: key_event.code as u32,
             pressed: key_event.pressed,
+            repeat: key_event.repeat,
+            modifiers: key_event.modifiers,
         },
         None => WasmKeyEvent {
             code: KeyCode::Undefined as u32,
             pressed: false,
+            repeat: false,
+            modifiers: 0,
         },
     };
     let result = result as u64;
@@ -192,7 +584,7 @@ pub fn taca_window_inner_size(mut env: FunctionEnvMut<System>, result: u32) {
     println!("taca_windowInnerSize({result})");
     let (system, mut store) = env.data_and_store_mut();
     let memory = system.memory.as_ref().unwrap().view(&mut store);
-    let size = system.window.as_ref().unwrap().inner_size();
+    let size = system.inner_size();
     let result = result as u64;
     memory.write(result, &size.width.to_le_bytes()).unwrap();
     memory
@@ -223,13 +615,51 @@ pub fn taca_window_set_title(mut env: FunctionEnvMut<System>, title: u32) {
     let title = WasmPtr::<u8>::new(title)
         .read_utf8_string_with_nul(&view)
         .unwrap();
-    let window = system.window.as_ref().unwrap();
-    window.set_title(title.as_str());
+    if let Some(window) = system.window.as_ref() {
+        window.set_title(title.as_str());
+    }
 }
 
-use crate::{gpu::gpu_window_listen, system::*};
-use wasmer::{Function, FunctionEnv, FunctionEnvMut, Store, Value, ValueType, WasmPtr, WasmRef};
+use crate::{
+    gamepad::{poll_gamepads, GamepadSource},
+    gpu::gpu_window_listen,
+    system::*,
+};
+use std::collections::HashSet;
+use wasmer::{
+    FunctionEnv, FunctionEnvMut, RuntimeError, Store, Value, ValueType, WasmPtr, WasmRef,
+};
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
 };
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_input_skips_chars_while_composing() {
+        let mut input = TextInput::default();
+        assert_eq!(input.char_event('a').unwrap().text, "a");
+        assert_eq!(input.char_event('\u{8}'), None);
+        let preedit = input.ime_event(&Ime::Preedit("に".into(), Some((0, 3))));
+        assert!(preedit.unwrap().preedit);
+        assert_eq!(input.char_event('に'), None);
+        let commit = input.ime_event(&Ime::Commit("日".into())).unwrap();
+        assert_eq!(commit.text, "日");
+        assert!(!commit.preedit);
+        assert_eq!(input.char_event('b').unwrap().text, "b");
+    }
+
+    #[test]
+    fn text_input_ends_composing_when_cleared_or_disabled() {
+        let mut input = TextInput::default();
+        input.ime_event(&Ime::Preedit("k".into(), None));
+        input.ime_event(&Ime::Preedit(String::new(), None));
+        assert!(input.char_event('k').is_some());
+        input.ime_event(&Ime::Preedit("k".into(), None));
+        assert_eq!(input.ime_event(&Ime::Disabled), None);
+        assert!(input.char_event('k').is_some());
+    }
+}
@@ -0,0 +1,33 @@
+;; Clears each frame to red, or to green once space is pressed, which is just
+;; enough to check frames and input end to end in `taca test`.
+(module
+  (import "env" "taca_windowListen" (func $windowListen (param i32 i32)))
+  (import "env" "taca_keyEvent" (func $keyEvent (param i32)))
+  (import "env" "taca_gpu_clear" (func $clear (param i32)))
+  (import "env" "taca_gpu_present" (func $present))
+  (memory (export "memory") 1)
+  ;; Key event at 0, then colors as rgba doubles.
+  (data (i32.const 16)
+    "\00\00\00\00\00\00\f0\3f" "\00\00\00\00\00\00\00\00"
+    "\00\00\00\00\00\00\00\00" "\00\00\00\00\00\00\f0\3f")
+  (data (i32.const 48)
+    "\00\00\00\00\00\00\00\00" "\00\00\00\00\00\00\f0\3f"
+    "\00\00\00\00\00\00\00\00" "\00\00\00\00\00\00\f0\3f")
+  (global $color (mut i32) (i32.const 16))
+  (func (export "windowListen") (param $type i32) (param $userdata i32)
+    ;; Key
+    (if (i32.eq (local.get $type) (i32.const 2))
+      (then
+        (call $keyEvent (i32.const 0))
+        ;; Space pressed
+        (if (i32.and
+              (i32.eq (i32.load (i32.const 0)) (i32.const 15))
+              (i32.load8_u (i32.const 4)))
+          (then (global.set $color (i32.const 48))))))
+    ;; Redraw
+    (if (i32.eq (local.get $type) (i32.const 3))
+      (then
+        (call $clear (global.get $color))
+        (call $present))))
+  (func (export "_start")
+    (call $windowListen (i32.const 0) (i32.const 0))))
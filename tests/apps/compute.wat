@@ -0,0 +1,60 @@
+;; Each frame dispatches a compute shader that fills a 4x4 storage texture with
+;; a checker of red and green over blue, then draws a full screen triangle
+;; that samples it in 16x12 pixel cells.
+(module
+  (import "env" "taca_windowListen" (func $windowListen (param i32 i32)))
+  (import "env" "taca_gpu_computeCreate" (func $computeCreate (param i32 i32) (result i32)))
+  (import "env" "taca_gpu_dispatch" (func $dispatch (param i32 i32 i32)))
+  (import "env" "taca_gpu_draw" (func $draw (param i32)))
+  (import "env" "taca_gpu_present" (func $present))
+  (import "env" "taca_gpu_shaderCreate" (func $shaderCreate (param i32) (result i32)))
+  (import "env" "taca_gpu_storageTextureCreate" (func $storageTextureCreate (param i32) (result i32)))
+  (import "env" "taca_gpu_vertexBufferCreate" (func $vertexBufferCreate (param i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; Texture info: RGBA8Unorm at binding 0, 4x4.
+  (data (i32.const 0)
+    "\12\00\00\00" "\00\00\00\00" "\04\00\00\00" "\04\00\00\00")
+  ;; Vertex buffer layout: stride 8, per vertex, 1 attribute at 64.
+  (data (i32.const 32)
+    "\08\00\00\00\00\00\00\00" "\00\00\00\00" "\01\00\00\00" "\40\00\00\00")
+  ;; Vertex attribute: Float32x2 at offset 0 for location 0.
+  (data (i32.const 64)
+    "\14\00\00\00" "\00\00\00\00" "\00\00\00\00\00\00\00\00" "\00\00\00\00")
+  ;; Full screen triangle at (-1, -1), (3, -1), (-1, 3).
+  (data (i32.const 96)
+    "\00\00\80\bf" "\00\00\80\bf" "\00\00\40\40" "\00\00\80\bf"
+    "\00\00\80\bf" "\00\00\40\40")
+  ;; Render shader, created first for the default pipeline.
+  (data (i32.const 256)
+    "@group(0) @binding(0) var image: texture_2d<f32>;\n"
+    "@vertex\n"
+    "fn vs_main(@location(0) position: vec2<f32>) -> @builtin(position) vec4<f32> {\n"
+    "    return vec4<f32>(position, 0.0, 1.0);\n"
+    "}\n"
+    "@fragment\n"
+    "fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {\n"
+    "    return textureLoad(image, vec2<i32>(position.xy) / vec2<i32>(16, 12), 0);\n"
+    "}\n\00")
+  ;; Compute shader.
+  (data (i32.const 1024)
+    "@group(0) @binding(0) var image: texture_storage_2d<rgba8unorm, write>;\n"
+    "@compute @workgroup_size(1)\n"
+    "fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {\n"
+    "    let color = vec4<f32>(f32(id.x % 2u), f32(id.y % 2u), 1.0, 1.0);\n"
+    "    textureStore(image, vec2<i32>(id.xy), color);\n"
+    "}\n\00")
+  (global $buffer (mut i32) (i32.const 0))
+  (func (export "windowListen") (param $type i32) (param $userdata i32)
+    ;; Redraw
+    (if (i32.eq (local.get $type) (i32.const 3))
+      (then
+        (call $dispatch (i32.const 4) (i32.const 4) (i32.const 1))
+        (call $draw (global.get $buffer))
+        (call $present))))
+  (func (export "_start")
+    (drop (call $shaderCreate (i32.const 256)))
+    (drop (call $computeCreate (call $shaderCreate (i32.const 1024)) (i32.const 0)))
+    (drop (call $storageTextureCreate (i32.const 0)))
+    (global.set $buffer
+      (call $vertexBufferCreate (i32.const 24) (i32.const 96) (i32.const 32)))
+    (call $windowListen (i32.const 0) (i32.const 0))))
@@ -0,0 +1,162 @@
+;; Pokes at the edges of the `--dir /rw` and `--dir-ro /ro` preopens, exiting
+;; with a code naming the first check that fails. The only arg is an absolute
+;; host path that must stay out of reach.
+(module
+  (import "wasi_snapshot_preview1" "args_get"
+    (func $args_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "args_sizes_get"
+    (func $args_sizes_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_close"
+    (func $fd_close (param i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_prestat_dir_name"
+    (func $fd_prestat_dir_name (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_prestat_get"
+    (func $fd_prestat_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_open"
+    (func $path_open
+      (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (memory (export "memory") 1)
+  ;; Scratch: prestat at 0, opened fd at 16, written size at 20, iovec at 24,
+  ;; dir name at 64, arg sizes at 128, arg pointers at 256, arg chars at 512.
+  (data (i32.const 2048) "/rw")
+  (data (i32.const 2056) "/ro")
+  (data (i32.const 2064) "made.txt")
+  (data (i32.const 2080) "data.txt")
+  (data (i32.const 2096) "../secret.txt")
+  (data (i32.const 2112) "ok")
+  (global $CREAT i32 (i32.const 1))
+  (global $TRUNC i32 (i32.const 8))
+  (global $READ i64 (i64.const 2))
+  (global $WRITE i64 (i64.const 64))
+
+  (func $check (param $ok i32) (param $code i32)
+    (if (i32.eqz (local.get $ok))
+      (then (call $proc_exit (local.get $code)))))
+
+  (func $equal (param $a i32) (param $b i32) (param $size i32) (result i32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.eqz (local.get $size)))
+        (if (i32.ne (i32.load8_u (local.get $a)) (i32.load8_u (local.get $b)))
+          (then (return (i32.const 0))))
+        (local.set $a (i32.add (local.get $a) (i32.const 1)))
+        (local.set $b (i32.add (local.get $b) (i32.const 1)))
+        (local.set $size (i32.sub (local.get $size) (i32.const 1)))
+        (br $next)))
+    (i32.const 1))
+
+  (func $is_preopen (param $fd i32) (result i32)
+    (i32.eqz (call $fd_prestat_get (local.get $fd) (i32.const 0))))
+
+  ;; Finds the preopen named like "/rw", with or without the slash.
+  (func $find (param $name i32) (param $size i32) (result i32)
+    (local $fd i32)
+    (local $found i32)
+    (local.set $fd (i32.const 3))
+    (loop $next
+      (if (call $is_preopen (local.get $fd))
+        (then
+          (local.set $found (i32.load (i32.const 4)))
+          (if (i32.le_u (local.get $found) (i32.const 64))
+            (then
+              (if (i32.eqz (call $fd_prestat_dir_name
+                    (local.get $fd) (i32.const 64) (local.get $found)))
+                (then
+                  (if (i32.eq (local.get $found) (local.get $size))
+                    (then
+                      (if (call $equal (i32.const 64) (local.get $name) (local.get $size))
+                        (then (return (local.get $fd))))))
+                  (if (i32.eq (local.get $found) (i32.sub (local.get $size) (i32.const 1)))
+                    (then
+                      (if (call $equal
+                            (i32.const 64)
+                            (i32.add (local.get $name) (i32.const 1))
+                            (local.get $found))
+                        (then (return (local.get $fd))))))))))))
+      (local.set $fd (i32.add (local.get $fd) (i32.const 1)))
+      (br_if $next (i32.lt_u (local.get $fd) (i32.const 64))))
+    (call $proc_exit (i32.const 10))
+    (unreachable))
+
+  ;; Returns the errno, leaving any opened fd at 16.
+  (func $open
+    (param $dir i32) (param $path i32) (param $size i32) (param $oflags i32)
+    (param $rights i64) (result i32)
+    (call $path_open
+      (local.get $dir) (i32.const 0) (local.get $path) (local.get $size)
+      (local.get $oflags) (local.get $rights) (local.get $rights) (i32.const 0)
+      (i32.const 16)))
+
+  ;; Returns the errno of writing "ok" to the fd at 16.
+  (func $write_ok (result i32)
+    (i32.store (i32.const 24) (i32.const 2112))
+    (i32.store (i32.const 28) (i32.const 2))
+    (call $fd_write (i32.load (i32.const 16)) (i32.const 24) (i32.const 1) (i32.const 20)))
+
+  (func (export "_start")
+    (local $rw i32)
+    (local $ro i32)
+    (local $fd i32)
+    (local $secret i32)
+    (local $secret_size i32)
+    ;; Secret path from the last arg, which ends with the arg chars.
+    (call $check (i32.eqz (call $args_sizes_get (i32.const 128) (i32.const 132))) (i32.const 20))
+    (call $check (i32.eq (i32.load (i32.const 128)) (i32.const 2)) (i32.const 21))
+    (call $check (i32.le_u (i32.load (i32.const 132)) (i32.const 1024)) (i32.const 22))
+    (call $check (i32.eqz (call $args_get (i32.const 256) (i32.const 512))) (i32.const 23))
+    (local.set $secret (i32.load (i32.const 260)))
+    (local.set $secret_size
+      (i32.sub
+        (i32.add (i32.const 511) (i32.load (i32.const 132)))
+        (local.get $secret)))
+    (local.set $rw (call $find (i32.const 2048) (i32.const 3)))
+    (local.set $ro (call $find (i32.const 2056) (i32.const 3)))
+    ;; Writable dirs take new files.
+    (call $check
+      (i32.eqz (call $open (local.get $rw) (i32.const 2064) (i32.const 8)
+        (i32.or (global.get $CREAT) (global.get $TRUNC)) (global.get $WRITE)))
+      (i32.const 11))
+    (call $check (i32.eqz (call $write_ok)) (i32.const 12))
+    (drop (call $fd_close (i32.load (i32.const 16))))
+    ;; Read only dirs can be read.
+    (call $check
+      (i32.eqz (call $open (local.get $ro) (i32.const 2080) (i32.const 8)
+        (i32.const 0) (global.get $READ)))
+      (i32.const 13))
+    (drop (call $fd_close (i32.load (i32.const 16))))
+    ;; But not created in.
+    (if (i32.eqz (call $open (local.get $ro) (i32.const 2064) (i32.const 8)
+          (global.get $CREAT) (global.get $WRITE)))
+      (then
+        (call $check (i32.ne (call $write_ok) (i32.const 0)) (i32.const 14))
+        (drop (call $fd_close (i32.load (i32.const 16))))))
+    ;; Or written to.
+    (if (i32.eqz (call $open (local.get $ro) (i32.const 2080) (i32.const 8)
+          (global.get $TRUNC) (global.get $WRITE)))
+      (then
+        (call $check (i32.ne (call $write_ok) (i32.const 0)) (i32.const 15))
+        (drop (call $fd_close (i32.load (i32.const 16))))))
+    ;; Parent dirs are out of reach.
+    (call $check
+      (i32.ne
+        (call $open (local.get $rw) (i32.const 2096) (i32.const 13)
+          (i32.const 0) (global.get $READ))
+        (i32.const 0))
+      (i32.const 16))
+    ;; As are absolute host paths from any preopen at all.
+    (local.set $fd (i32.const 3))
+    (loop $next
+      (if (call $is_preopen (local.get $fd))
+        (then
+          (call $check
+            (i32.ne
+              (call $open (local.get $fd) (local.get $secret) (local.get $secret_size)
+                (i32.const 0) (global.get $READ))
+              (i32.const 0))
+            (i32.const 17))))
+      (local.set $fd (i32.add (local.get $fd) (i32.const 1)))
+      (br_if $next (i32.lt_u (local.get $fd) (i32.const 64)))))
+)
@@ -0,0 +1,98 @@
+;; Each frame draws a checker to parts of a 4x4 render target that clears to
+;; yellow, then samples it over the frame in 16x12 pixel cells. The target
+;; gets two passes, so the second has to load what the first drew.
+(module
+  (import "env" "taca_windowListen" (func $windowListen (param i32 i32)))
+  (import "env" "taca_gpu_clear" (func $clear (param i32)))
+  (import "env" "taca_gpu_draw" (func $draw (param i32)))
+  (import "env" "taca_gpu_pipelineCreate" (func $pipelineCreate (param i32 i32) (result i32)))
+  (import "env" "taca_gpu_pipelineUse" (func $pipelineUse (param i32)))
+  (import "env" "taca_gpu_present" (func $present))
+  (import "env" "taca_gpu_renderTargetCreate" (func $renderTargetCreate (param i32) (result i32)))
+  (import "env" "taca_gpu_renderTargetUse" (func $renderTargetUse (param i32 i32)))
+  (import "env" "taca_gpu_shaderCreate" (func $shaderCreate (param i32) (result i32)))
+  (import "env" "taca_gpu_vertexBufferCreate" (func $vertexBufferCreate (param i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; Texture info: RGBA8Unorm at binding 0, 4x4.
+  (data (i32.const 0)
+    "\12\00\00\00" "\00\00\00\00" "\04\00\00\00" "\04\00\00\00")
+  ;; Yellow as rgba doubles.
+  (data (i32.const 16)
+    "\00\00\00\00\00\00\f0\3f" "\00\00\00\00\00\00\f0\3f"
+    "\00\00\00\00\00\00\00\00" "\00\00\00\00\00\00\f0\3f")
+  ;; Vertex buffer layout: stride 8, per vertex, 1 attribute at 80.
+  (data (i32.const 48)
+    "\08\00\00\00\00\00\00\00" "\00\00\00\00" "\01\00\00\00" "\50\00\00\00")
+  ;; Vertex attribute: Float32x2 at offset 0 for location 0.
+  (data (i32.const 80)
+    "\14\00\00\00" "\00\00\00\00" "\00\00\00\00\00\00\00\00" "\00\00\00\00")
+  ;; Full screen triangle at (-1, -1), (3, -1), (-1, 3).
+  (data (i32.const 112)
+    "\00\00\80\bf" "\00\00\80\bf" "\00\00\40\40" "\00\00\80\bf"
+    "\00\00\80\bf" "\00\00\40\40")
+  ;; Left half from x -1 to 0.
+  (data (i32.const 144)
+    "\00\00\80\bf" "\00\00\80\bf" "\00\00\00\00" "\00\00\80\bf"
+    "\00\00\80\bf" "\00\00\80\3f" "\00\00\00\00" "\00\00\80\bf"
+    "\00\00\00\00" "\00\00\80\3f" "\00\00\80\bf" "\00\00\80\3f")
+  ;; Bottom right quarter from x 0 to 1 and y -1 to 0.
+  (data (i32.const 192)
+    "\00\00\00\00" "\00\00\80\bf" "\00\00\80\3f" "\00\00\80\bf"
+    "\00\00\00\00" "\00\00\00\00" "\00\00\80\3f" "\00\00\80\bf"
+    "\00\00\80\3f" "\00\00\00\00" "\00\00\00\00" "\00\00\00\00")
+  ;; Checker shader, created first for the default pipeline.
+  (data (i32.const 256)
+    "@vertex\n"
+    "fn vs_main(@location(0) position: vec2<f32>) -> @builtin(position) vec4<f32> {\n"
+    "    return vec4<f32>(position, 0.0, 1.0);\n"
+    "}\n"
+    "@fragment\n"
+    "fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {\n"
+    "    let cell = vec2<u32>(position.xy) % vec2<u32>(2u, 2u);\n"
+    "    return vec4<f32>(f32(cell.x), f32(cell.y), 1.0, 1.0);\n"
+    "}\n\00")
+  ;; Sampling shader.
+  (data (i32.const 1024)
+    "@group(0) @binding(0) var image: texture_2d<f32>;\n"
+    "@vertex\n"
+    "fn vs_main(@location(0) position: vec2<f32>) -> @builtin(position) vec4<f32> {\n"
+    "    return vec4<f32>(position, 0.0, 1.0);\n"
+    "}\n"
+    "@fragment\n"
+    "fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {\n"
+    "    return textureLoad(image, vec2<i32>(position.xy) / vec2<i32>(16, 12), 0);\n"
+    "}\n\00")
+  (global $target (mut i32) (i32.const 0))
+  (global $sample (mut i32) (i32.const 0))
+  (global $full (mut i32) (i32.const 0))
+  (global $left (mut i32) (i32.const 0))
+  (global $corner (mut i32) (i32.const 0))
+  (func (export "windowListen") (param $type i32) (param $userdata i32)
+    ;; Redraw
+    (if (i32.eq (local.get $type) (i32.const 3))
+      (then
+        (call $pipelineUse (i32.const 0))
+        (call $renderTargetUse (global.get $target) (i32.const 0))
+        (call $draw (global.get $left))
+        ;; Switching away and back starts a new pass on the target.
+        (call $renderTargetUse (i32.const 0) (i32.const 0))
+        (call $renderTargetUse (global.get $target) (i32.const 0))
+        (call $draw (global.get $corner))
+        (call $renderTargetUse (i32.const 0) (i32.const 0))
+        (call $pipelineUse (global.get $sample))
+        (call $draw (global.get $full))
+        (call $present))))
+  (func (export "_start")
+    (drop (call $shaderCreate (i32.const 256)))
+    (global.set $sample
+      (call $pipelineCreate (call $shaderCreate (i32.const 1024)) (i32.const 0)))
+    (global.set $target (call $renderTargetCreate (i32.const 0)))
+    (global.set $full
+      (call $vertexBufferCreate (i32.const 24) (i32.const 112) (i32.const 48)))
+    (global.set $left
+      (call $vertexBufferCreate (i32.const 48) (i32.const 144) (i32.const 48)))
+    (global.set $corner
+      (call $vertexBufferCreate (i32.const 48) (i32.const 192) (i32.const 48)))
+    ;; Targets clear like the frame.
+    (call $clear (i32.const 16))
+    (call $windowListen (i32.const 0) (i32.const 0))))
@@ -0,0 +1,36 @@
+//! Golden image runs of small apps through `taca test`, using the fallback
+//! adapter so they also work without a gpu.
+
+use std::{path::Path, process::Command};
+
+fn run_golden(app: &str, name: &str) {
+    let golden = Path::new("tests/golden");
+    let output = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let status = Command::new(env!("CARGO_BIN_EXE_taca"))
+        .arg("test")
+        .arg(Path::new("tests/apps").join(app))
+        .arg(golden.join(format!("{name}.taca-test")))
+        .arg("--reference")
+        .arg(golden.join(name))
+        .arg("--output")
+        .arg(&output)
+        .args(["--width", "64", "--height", "48"])
+        .status()
+        .unwrap();
+    assert!(status.success(), "see {}", output.display());
+}
+
+#[test]
+fn clear() {
+    run_golden("clear.wat", "clear");
+}
+
+#[test]
+fn compute() {
+    run_golden("compute.wat", "compute");
+}
+
+#[test]
+fn target() {
+    run_golden("target.wat", "target");
+}
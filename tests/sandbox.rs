@@ -0,0 +1,36 @@
+//! Checks that `--dir` and `--dir-ro` give apps only what they say.
+
+use std::{fs, path::Path, process::Command};
+
+#[test]
+fn dirs() {
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("sandbox");
+    let _ = fs::remove_dir_all(&root);
+    let rw = root.join("rw");
+    let ro = root.join("ro");
+    fs::create_dir_all(&rw).unwrap();
+    fs::create_dir_all(&ro).unwrap();
+    fs::write(ro.join("data.txt"), "data").unwrap();
+    let secret = root.join("secret.txt");
+    fs::write(&secret, "secret").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_taca"))
+        .args(["run", "--headless", "--dir"])
+        .arg(format!("{}:/rw", rw.display()))
+        .arg("--dir-ro")
+        .arg(format!("{}:/ro", ro.display()))
+        .arg("tests/apps/sandbox.wat")
+        .arg("--")
+        .arg(fs::canonicalize(&secret).unwrap())
+        .output()
+        .unwrap();
+    // Exit codes from the app name the failed check.
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(fs::read_to_string(rw.join("made.txt")).unwrap(), "ok");
+    assert!(!ro.join("made.txt").exists());
+    assert_eq!(fs::read_to_string(ro.join("data.txt")).unwrap(), "data");
+    assert_eq!(fs::read_to_string(&secret).unwrap(), "secret");
+}